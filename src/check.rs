@@ -0,0 +1,117 @@
+use crate::{datacube::DataCube, errors::HRMTestError, levels::Level, program::{Program, STEP_LIMIT}, rng::Rng};
+
+/// runs a program on a single inbox of a level, and compares its outbox against what
+/// management expects. returns the number of steps the program took.
+pub fn check_inbox(program: &Program, level: &Level, inbox: &[DataCube]) -> Result<usize, HRMTestError> {
+    let (steps, outbox) = program.simulate_with(inbox.to_vec(), level.initial_floor(), Some(STEP_LIMIT))
+        .map_err(HRMTestError::RuntimeError)?;
+    
+    let expected = level.expected_outbox(inbox);
+    
+    for (index, (actual, expected)) in outbox.iter().zip(expected.iter()).enumerate() {
+        if actual != expected {
+            return Err(HRMTestError::BadOutbox { actual: actual.clone(), expected: expected.clone(), index });
+        }
+    }
+    
+    if let Some(actual) = outbox.get(expected.len()) {
+        return Err(HRMTestError::TooMuchOutBox { actual: actual.clone(), index: expected.len() });
+    }
+    
+    if outbox.len() < expected.len() {
+        return Err(HRMTestError::NotEnoughOutBox { actual: outbox.len(), expected: expected.len() });
+    }
+    
+    Ok(steps)
+}
+
+/// checks that a program solves a level, the same way the game does.
+/// 
+/// the program is first run on one inbox, and any problem with it is reported directly.
+/// after that, it is run on `robustness_inboxes` more inboxes, and any problem with
+/// those is reported as a [`HRMTestError::SolutionNotRobust`] along with the inbox.
+pub fn check(program: &Program, level: &Level, robustness_inboxes: usize, rng: &mut Rng) -> Result<(), HRMTestError> {
+    score(program, level, robustness_inboxes + 1, rng).map(|_| ())
+}
+
+/// how well a (correct) program does on a level.
+#[derive(Debug, Clone)]
+pub struct Score {
+    /// the number of instructions in the program (labels and comments don't count)
+    pub size: usize,
+    
+    /// the number of steps the program took on each inbox, from fastest to slowest
+    pub steps: Vec<usize>,
+    
+    pub size_challenge: usize,
+    pub speed_challenge: usize,
+}
+
+impl Score {
+    /// the average number of steps, which is what the game uses for the speed challenge.
+    pub fn mean(&self) -> f64 {
+        self.steps.iter().sum::<usize>() as f64 / self.steps.len() as f64
+    }
+    
+    pub fn min(&self) -> usize {
+        self.steps[0]
+    }
+    
+    pub fn max(&self) -> usize {
+        self.steps[self.steps.len() - 1]
+    }
+    
+    /// the smallest step count that at least `percent`% of the runs stayed within.
+    pub fn percentile(&self, percent: f64) -> usize {
+        let rank = (percent / 100.0 * self.steps.len() as f64).ceil() as usize;
+        self.steps[rank.clamp(1, self.steps.len()) - 1]
+    }
+    
+    pub fn meets_size_challenge(&self) -> bool {
+        self.size <= self.size_challenge
+    }
+    
+    pub fn meets_speed_challenge(&self) -> bool {
+        self.mean() <= self.speed_challenge as f64
+    }
+}
+
+impl std::fmt::Display for Score {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let verdict = |met: bool| if met { "met" } else { "NOT met" };
+        
+        writeln!(f, "size:  {} (challenge: {}, {})", self.size, self.size_challenge, verdict(self.meets_size_challenge()))?;
+        writeln!(f, "speed: {:.2} on average over {} inboxes (challenge: {}, {})",
+            self.mean(), self.steps.len(), self.speed_challenge, verdict(self.meets_speed_challenge()))?;
+        write!(f, "       min {}, median {}, 90th percentile {}, max {}",
+            self.min(), self.percentile(50.0), self.percentile(90.0), self.max())
+    }
+}
+
+/// scores a program on a level by running it on `runs` randomly generated inboxes.
+/// 
+/// the program has to actually solve the level for its score to mean anything, so
+/// this fails in the same way as [`check`] if it gets any of the outboxes wrong.
+pub fn score(program: &Program, level: &Level, runs: usize, rng: &mut Rng) -> Result<Score, HRMTestError> {
+    assert!(runs > 0, "a program needs to be run at least once to be scored");
+    
+    let mut steps = Vec::with_capacity(runs);
+    
+    for run in 0..runs {
+        let inbox = level.generate_inbox(rng);
+        match check_inbox(program, level, &inbox) {
+            Ok(count) => steps.push(count),
+            Err(error) if run == 0 => return Err(error),
+            Err(cause) => return Err(HRMTestError::SolutionNotRobust { inbox, cause: Box::new(cause) }),
+        }
+    }
+    
+    steps.sort();
+    
+    Ok(Score {
+        size: program.instructions.len(),
+        steps,
+        size_challenge: level.size_challenge,
+        speed_challenge: level.speed_challenge,
+    })
+}
@@ -0,0 +1,288 @@
+//! parsing the command line.
+//!
+//! (this is all done by hand, since it's simple enough to not need a dependency for)
+
+use std::path::PathBuf;
+
+use hrm_optimizer::{levels::Level, optimize::pass_manager::{UnknownPass, PASSES}, DataCube, OptimizationGoal};
+
+pub const USAGE: &str = "\
+Usage: hrm-optimizer <command> [options] [file]
+
+Commands:
+    optimize    optimize the program, and print the result
+    run         run the program on an inbox, and print the outbox
+    check       check that the program solves a level (needs --level)
+    cfg         print the control flow graph of the program, with its dominators and loops
+                (or as DOT with --dot, or as IR with --ir)
+    fmt         reformat the program, the same way the game would
+    stats       print some statistics about the program, before and after optimizing it
+
+The program is read from [file], or from stdin if it's missing or `-`.
+
+Options:
+    -o, --output <file>     write the program to <file> instead of stdout (optimize, fmt)
+    -l, --level <number>    the level the program is for (this sets up the floor, and the inboxes)
+    -f, --floor <floor>     the initial floor, as `<size>` or `<size>:<tile>=<value>,...`
+                            (e.g. `16:14=0,15=4`), which overrides the level's floor
+    -O0                     don't optimize at all
+    -Os                     run every pass, and prefer smaller programs over faster ones
+    -Ospeed                 run every pass, and prefer faster programs over smaller ones (the default)
+        --passes <passes>   only run these passes, in this order (e.g. `simplify-jumps,dce,peephole`),
+                            out of: simplify-jumps, dce, merge-blocks, empty-blocks, merge-tails, peephole, dse,
+                            constprop, ranges, thread-jumps, rotate-loops, layout
+    -i, --inbox <values>    the inbox to run the program on, e.g. `1,-3,A` (run)
+        --inbox-file <file> read the inbox from <file> instead (run)
+        --runs <n>          how many random inboxes to test with (check, stats; default 100)
+        --seed <n>          the seed for generating random inboxes (default 0)
+        --optimized         optimize the program first (run, check, cfg)
+        --dot               print the control flow graph in graphviz's DOT format (cfg)
+        --ir                print the control flow graph in the textual IR that pass tests are written in (cfg)
+        --dot-passes <dir>  write the control flow graph as a DOT file to <dir> before the first pass,
+                            and after every pass that changes it (optimize, stats, --optimized)
+        --annotate          say which lines of the input each instruction came from (optimize)
+        --remarks <format>  explain every change the optimizer makes, as `text` or `json` (on stderr)
+        --verify-passes     check that every optimization pass keeps the program's behavior
+    -v, --verbose           print more about what's happening (can be repeated)
+    -q, --quiet             only print errors
+    -h, --help              print this message
+
+Exit codes:
+    0   success
+    1   the program failed (a runtime error, a failed check, or a broken optimization pass)
+    2   bad command line arguments
+    3   the program couldn't be read or parsed, or the output couldn't be written
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    Optimize,
+    Run,
+    Check,
+    Cfg,
+    Fmt,
+    Stats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemarksFormat {
+    Text,
+    Json,
+}
+
+/// where a run's inbox comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InboxSource {
+    Values(Vec<DataCube>),
+    File(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub command: Command,
+    
+    /// the program to read, or `None` for stdin
+    pub input: Option<PathBuf>,
+    
+    /// where to write the program to, or `None` for stdout
+    pub output: Option<PathBuf>,
+    
+    pub level: Option<&'static Level>,
+    pub floor: Option<Vec<Option<DataCube>>>,
+    pub goal: OptimizationGoal,
+    
+    /// the passes to run instead of all of them (`-O0` is an empty list)
+    pub passes: Option<Vec<&'static str>>,
+    pub inbox: Option<InboxSource>,
+    pub runs: usize,
+    pub seed: u64,
+    pub optimized: bool,
+    pub verify_passes: bool,
+    pub remarks: Option<RemarksFormat>,
+    pub annotate: bool,
+    pub dot: bool,
+    pub ir: bool,
+    
+    /// the directory to write a DOT file of the graph to after each pass
+    pub dot_passes: Option<PathBuf>,
+    
+    /// 0 for `--quiet`, 1 by default, and one more for each `--verbose`
+    pub verbosity: u8,
+}
+
+impl Options {
+    /// the floor to run the program with: `--floor` if there is one, then the level's floor, and otherwise nothing.
+    pub fn initial_floor(&self) -> Vec<Option<DataCube>> {
+        match (&self.floor, self.level) {
+            (Some(floor), _) => floor.clone(),
+            (None, Some(level)) => level.initial_floor(),
+            (None, None) => Vec::new(),
+        }
+    }
+}
+
+/// what `--help` asks for, or why the arguments don't make sense.
+#[derive(Debug)]
+pub enum ArgsError {
+    Help,
+    Invalid(String),
+}
+
+impl std::fmt::Display for ArgsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Help => f.write_str(USAGE),
+            Self::Invalid(message) => writeln!(f, "error: {message}\n(run with `--help` to see how to use this)"),
+        }
+    }
+}
+
+fn parse_cube(value: &str) -> Result<DataCube, String> {
+    let invalid = || format!("`{value}` isn't a number between -999 and 999, or a letter between A and Z");
+    
+    match value.parse::<i32>() {
+        Ok(n) => DataCube::from_number(n).map_err(|_| invalid()),
+        Err(_) => {
+            let mut chars = value.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => DataCube::from_char(c).ok_or_else(invalid),
+                _ => Err(invalid()),
+            }
+        },
+    }
+}
+
+/// parses an inbox, with the values separated by commas and/or whitespace.
+pub fn parse_inbox(values: &str) -> Result<Vec<DataCube>, String> {
+    values.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|value| !value.is_empty())
+        .map(parse_cube)
+        .collect()
+}
+
+/// parses a floor like `16:14=0,15=4`.
+fn parse_floor(floor: &str) -> Result<Vec<Option<DataCube>>, String> {
+    let (size, tiles) = floor.split_once(':').unwrap_or((floor, ""));
+    
+    let size: usize = size.trim().parse().map_err(|_| format!("`{size}` isn't a valid floor size"))?;
+    let mut result = vec![None; size];
+    
+    for tile in tiles.split(',').map(str::trim).filter(|tile| !tile.is_empty()) {
+        let (address, value) = tile.split_once('=').ok_or_else(|| format!("`{tile}` should look like `<tile>=<value>`"))?;
+        let address: usize = address.trim().parse().map_err(|_| format!("`{address}` isn't a valid tile address"))?;
+        if address >= size {
+            return Err(format!("tile {address} isn't on a floor with {size} tiles"));
+        }
+        result[address] = Some(parse_cube(value.trim())?);
+    }
+    
+    Ok(result)
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, ArgsError> {
+    let invalid = |message: String| ArgsError::Invalid(message);
+    
+    let mut args = args.into_iter();
+    
+    let mut command = None;
+    let mut options = Options {
+        command: Command::Optimize,
+        input: None,
+        output: None,
+        level: None,
+        floor: None,
+        goal: OptimizationGoal::Speed,
+        passes: None,
+        inbox: None,
+        runs: 100,
+        seed: 0,
+        optimized: false,
+        verify_passes: false,
+        remarks: None,
+        annotate: false,
+        dot: false,
+        ir: false,
+        dot_passes: None,
+        verbosity: 1,
+    };
+    
+    while let Some(arg) = args.next() {
+        // (`--option=value` is the same as `--option value`)
+        let (flag, inline_value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => (flag.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || inline_value.clone().or_else(|| args.next())
+            .ok_or_else(|| invalid(format!("`{flag}` needs a value")));
+        
+        match flag.as_str() {
+            "-h" | "--help" => return Err(ArgsError::Help),
+            "-o" | "--output" => options.output = Some(value()?.into()),
+            "-l" | "--level" => {
+                let number = value()?;
+                options.level = Some(number.parse().ok().and_then(Level::get)
+                    .ok_or_else(|| invalid(format!("there's no level `{number}`")))?);
+            },
+            "-f" | "--floor" => options.floor = Some(parse_floor(&value()?).map_err(invalid)?),
+            "-O0" => options.passes = Some(Vec::new()),
+            "-Os" => (options.goal, options.passes) = (OptimizationGoal::Size, None),
+            "-Ospeed" => (options.goal, options.passes) = (OptimizationGoal::Speed, None),
+            "--passes" => {
+                let passes = value()?;
+                options.passes = Some(passes.split(',').map(str::trim).filter(|pass| !pass.is_empty())
+                    .map(|pass| PASSES.iter().find(|(name, _)| *name == pass).map(|(name, _)| *name)
+                        .ok_or_else(|| invalid(UnknownPass(pass.to_string()).to_string())))
+                    .collect::<Result<_, _>>()?);
+            },
+            "-i" | "--inbox" => options.inbox = Some(InboxSource::Values(parse_inbox(&value()?).map_err(invalid)?)),
+            "--inbox-file" => options.inbox = Some(InboxSource::File(value()?.into())),
+            "--runs" => {
+                let runs = value()?;
+                options.runs = runs.parse().ok().filter(|&runs| runs > 0)
+                    .ok_or_else(|| invalid(format!("`{runs}` isn't a valid number of runs")))?;
+            },
+            "--seed" => {
+                let seed = value()?;
+                options.seed = seed.parse().map_err(|_| invalid(format!("`{seed}` isn't a valid seed")))?;
+            },
+            "--optimized" => options.optimized = true,
+            "--verify-passes" => options.verify_passes = true,
+            "--annotate" => options.annotate = true,
+            "--dot" => options.dot = true,
+            "--ir" => options.ir = true,
+            "--dot-passes" => options.dot_passes = Some(value()?.into()),
+            "--remarks" => options.remarks = Some(match value()?.as_str() {
+                "text" => RemarksFormat::Text,
+                "json" => RemarksFormat::Json,
+                format => return Err(invalid(format!("unknown remarks format `{format}` (expected `text` or `json`)"))),
+            }),
+            "-v" | "--verbose" => options.verbosity += 1,
+            "-vv" => options.verbosity += 2,
+            "-q" | "--quiet" => options.verbosity = 0,
+            
+            _ if flag.starts_with('-') && flag != "-" => return Err(invalid(format!("unknown option `{flag}`"))),
+            
+            _ if command.is_none() => command = Some(match flag.as_str() {
+                "optimize" => Command::Optimize,
+                "run" => Command::Run,
+                "check" => Command::Check,
+                "cfg" => Command::Cfg,
+                "fmt" => Command::Fmt,
+                "stats" => Command::Stats,
+                other => return Err(invalid(format!("unknown command `{other}`"))),
+            }),
+            _ if options.input.is_none() => {
+                if arg != "-" { options.input = Some(arg.into()) }
+            },
+            _ => return Err(invalid(format!("unexpected argument `{arg}`"))),
+        }
+    }
+    
+    options.command = command.ok_or_else(|| invalid("no command given".to_string()))?;
+    
+    if options.command == Command::Check && options.level.is_none() {
+        return Err(invalid("`check` needs a level to check against (use `--level`)".to_string()));
+    }
+    
+    Ok(options)
+}
@@ -13,7 +13,7 @@ impl DataCube {
     pub fn from_number<T: TryInto<i16>>(n: T) -> Result<Self, HRMRuntimeError> {
         let n = TryInto::<i16>::try_into(n).map_err(|_| HRMRuntimeError::Overflow)?;
         
-        if n < -999 || n > 999 {
+        if !(-999..=999).contains(&n) {
             return Err(HRMRuntimeError::Overflow)
         }
         
@@ -41,5 +41,10 @@ impl std::fmt::Debug for DataCube {
 }
 
 impl std::fmt::Display for DataCube {
-    fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> { todo!() }
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Number(x) => write!(f, "{x}"),
+            Self::Letter(x) => write!(f, "{}", *x as char),
+        }
+    }
 }
@@ -0,0 +1,242 @@
+//! differential testing of the optimizer: run the original and the optimized program
+//! on lots of inboxes, and make sure they do the same thing.
+
+use crate::{
+    datacube::DataCube,
+    errors::HRMRuntimeFault,
+    optimize::control_flow_graph::ProgramControlFlowGraph,
+    program::{Program, STEP_LIMIT},
+    rng::Rng,
+};
+
+/// what happened when a program was run on an inbox.
+pub type Outcome = Result<Vec<DataCube>, Box<HRMRuntimeFault>>;
+
+/// how picky to be about programs that crash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorBehavior {
+    /// the optimized program has to crash with the same error, after outboxing the same things.
+    Strict,
+    
+    /// runtime errors in the original program are treated as undefined behavior, so the optimized
+    /// program only has to agree with whatever the original outboxed before it crashed.
+    Undefined,
+}
+
+/// an inbox that the original and optimized programs don't agree on.
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    pub inbox: Vec<DataCube>,
+    pub original: Outcome,
+    pub optimized: Outcome,
+}
+
+impl std::fmt::Display for Divergence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        fn write_cubes(f: &mut std::fmt::Formatter<'_>, cubes: &[DataCube]) -> Result<(), std::fmt::Error> {
+            f.write_str("[")?;
+            for (i, cube) in cubes.iter().enumerate() {
+                if i != 0 { f.write_str(", ")?; }
+                write!(f, "{cube}")?;
+            }
+            f.write_str("]")
+        }
+        
+        fn write_outcome(f: &mut std::fmt::Formatter<'_>, outcome: &Outcome) -> Result<(), std::fmt::Error> {
+            match outcome {
+                Ok(outbox) => {
+                    f.write_str("outbox ")?;
+                    write_cubes(f, outbox)
+                },
+                Err(fault) => {
+                    f.write_str("outbox ")?;
+                    write_cubes(f, &fault.outbox)?;
+                    write!(f, ", then {}", fault.error)
+                },
+            }
+        }
+        
+        f.write_str("the programs behave differently on the inbox ")?;
+        write_cubes(f, &self.inbox)?;
+        f.write_str("\n  original:  ")?;
+        write_outcome(f, &self.original)?;
+        f.write_str("\n  optimized: ")?;
+        write_outcome(f, &self.optimized)
+    }
+}
+
+impl std::error::Error for Divergence {}
+
+/// a [`Divergence`] that showed up right after a specific optimization pass ran.
+#[derive(Debug, Clone)]
+pub struct BrokenPass {
+    pub pass: &'static str,
+    pub divergence: Divergence,
+}
+
+impl std::fmt::Display for BrokenPass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "the {} pass changed the behavior of the program: {}", self.pass, self.divergence)
+    }
+}
+
+impl std::error::Error for BrokenPass {}
+
+/// generates inboxes to compare programs with.
+///
+/// the first few are fixed edge cases (an empty inbox, zeroes, the biggest and smallest
+/// numbers, letters), and the rest are random mixes of small numbers, boundary values and letters.
+pub fn generate_inboxes(rng: &mut Rng, count: usize) -> Vec<Vec<DataCube>> {
+    let n = |x: i16| DataCube::Number(x);
+    let l = |c: u8| DataCube::Letter(c);
+    
+    let edge_cases = vec![
+        vec![],
+        vec![n(0)],
+        vec![n(1)],
+        vec![n(-1)],
+        vec![n(999)],
+        vec![n(-999)],
+        vec![l(b'A')],
+        vec![l(b'Z')],
+        vec![n(0), n(0)],
+        vec![n(999), n(999)],
+        vec![n(-999), n(999)],
+        vec![n(3), n(-3), n(0)],
+        vec![l(b'A'), l(b'B'), n(0)],
+    ];
+    
+    let mut inboxes: Vec<_> = edge_cases.into_iter().take(count).collect();
+    
+    while inboxes.len() < count {
+        let length = rng.range(1, 12);
+        let inbox = (0..length).map(|_| match rng.range(0, 9) {
+            0 => rng.choose(&[n(0), n(1), n(-1), n(999), n(-999)]),
+            1 => rng.number(-999, 999),
+            2 | 3 => rng.letter(),
+            _ => rng.number(-9, 9),
+        }).collect();
+        inboxes.push(inbox);
+    }
+    
+    inboxes
+}
+
+fn run(program: &Program, inbox: &[DataCube]) -> Outcome {
+    program.simulate_with(inbox.to_vec(), program.initial_floor.clone(), Some(STEP_LIMIT))
+        .map(|(_, outbox)| outbox)
+}
+
+fn agrees(original: &Outcome, optimized: &Outcome, errors: ErrorBehavior) -> bool {
+    match (original, optimized, errors) {
+        (Ok(expected), Ok(actual), _) => expected == actual,
+        (Err(expected), Err(actual), ErrorBehavior::Strict) => expected.error == actual.error && expected.outbox == actual.outbox,
+        (_, _, ErrorBehavior::Strict) => false,
+        (Ok(_), Err(_), ErrorBehavior::Undefined) => false,
+        (Err(expected), actual, ErrorBehavior::Undefined) => {
+            let actual = match actual {
+                Ok(outbox) => outbox,
+                Err(fault) => &fault.outbox,
+            };
+            // the optimized program is allowed to crash earlier or keep going, but it can't
+            // outbox anything different from what the original did before it crashed
+            expected.outbox.iter().zip(actual.iter()).all(|(a, b)| a == b)
+        },
+    }
+}
+
+/// runs both programs on every inbox, and returns the first one where they don't agree.
+pub fn check_equivalence(original: &Program, optimized: &Program, inboxes: &[Vec<DataCube>], errors: ErrorBehavior) -> Result<(), Divergence> {
+    for inbox in inboxes {
+        let expected = run(original, inbox);
+        let actual = run(optimized, inbox);
+        
+        if !agrees(&expected, &actual, errors) {
+            return Err(Divergence {
+                inbox: inbox.clone(),
+                original: expected,
+                optimized: actual,
+            });
+        }
+    }
+    
+    Ok(())
+}
+
+/// checks the control flow graph against the original program after every optimization pass,
+/// so that a miscompilation can be blamed on the pass that caused it.
+pub struct PassVerifier {
+    inboxes: Vec<Vec<DataCube>>,
+    
+    /// what the original program does on each inbox (so it only has to be run once)
+    expected: Vec<Outcome>,
+    errors: ErrorBehavior,
+}
+
+impl PassVerifier {
+    pub fn new(original: &Program, inboxes: Vec<Vec<DataCube>>, errors: ErrorBehavior) -> Self {
+        let expected = inboxes.iter().map(|inbox| run(original, inbox)).collect();
+        Self { inboxes, expected, errors }
+    }
+    
+    /// checks that the graph still behaves like the original program, right after `pass` ran on it.
+    pub fn verify(&self, pass: &'static str, graph: &ProgramControlFlowGraph) -> Result<(), BrokenPass> {
+        let optimized: Program = graph.into();
+        
+        for (inbox, expected) in self.inboxes.iter().zip(self.expected.iter()) {
+            let actual = run(&optimized, inbox);
+            
+            if !agrees(expected, &actual, self.errors) {
+                return Err(BrokenPass {
+                    pass,
+                    divergence: Divergence {
+                        inbox: inbox.clone(),
+                        original: expected.clone(),
+                        optimized: actual,
+                    },
+                });
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn equivalence_checks_find_differences() {
+        let program = |body: &str| {
+            let mut program = Program::from_asm(&format!("-- HUMAN RESOURCE MACHINE PROGRAM --\n\na:\n{body}    JUMP     a\n")).unwrap();
+            program.initial_floor = vec![None];
+            program
+        };
+        let echo = program("    INBOX\n    OUTBOX\n");
+        let unrolled_echo = program("    INBOX\n    OUTBOX\n    INBOX\n    OUTBOX\n");
+        let no_zeros = program("    INBOX\n    JUMPZ    a\n    OUTBOX\n");
+        // (the same as `echo`, except that it crashes on letters and overflows on 999)
+        let fragile_echo = program("    INBOX\n    COPYTO   0\n    BUMPUP   0\n    BUMPDN   0\n    OUTBOX\n");
+        
+        let inboxes = generate_inboxes(&mut Rng::new(0), 50);
+        let check = |a, b, errors| check_equivalence(a, b, &inboxes, errors);
+        
+        assert!(check(&echo, &unrolled_echo, ErrorBehavior::Strict).is_ok());
+        
+        let divergence = check(&echo, &no_zeros, ErrorBehavior::Strict).unwrap_err();
+        assert_eq!(divergence.inbox, [DataCube::Number(0)]);
+        assert_eq!(divergence.to_string(), "the programs behave differently on the inbox [0]\n  original:  outbox [0]\n  optimized: outbox []");
+        
+        // the original crashing is only fine if it's undefined behavior, and the other way around never is
+        let divergence = check(&fragile_echo, &echo, ErrorBehavior::Strict).unwrap_err();
+        assert_eq!(divergence.inbox, [DataCube::Number(999)]);
+        assert!(check(&fragile_echo, &echo, ErrorBehavior::Undefined).is_ok());
+        assert!(check(&echo, &fragile_echo, ErrorBehavior::Undefined).is_err());
+        
+        // (but everything outboxed before the crash still has to match)
+        let inbox = [vec![DataCube::Number(0), DataCube::Letter(b'A')]];
+        assert!(check_equivalence(&fragile_echo, &echo, &inbox, ErrorBehavior::Undefined).is_ok());
+        assert!(check_equivalence(&fragile_echo, &no_zeros, &inbox, ErrorBehavior::Undefined).is_err());
+    }
+}
@@ -1,59 +1,139 @@
-use crate::datacube::DataCube;
+use crate::{datacube::DataCube, instruction::Instruction, program::format_lines};
 
 /// When optimizing a program, it is advantageous to treat
 /// these as "undefined behavior" and assume they never happen.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum HRMRuntimeError {
     /// Empty value! You can't {operation} with an empty tile on the floor! Try writing something to that tile first.
     /// 
     /// operation: COPYFROM, ADD, SUB, BUMPUP, BUMPDN
-    EmptyFloor,
+    EmptyFloor { operation: &'static str },
     
     /// Empty value! You can't {operation} with empty hands!
     /// 
     /// operation: OUTBOX, COPYTO, JUMPZ, JUMPN, ADD, SUB, 
-    EmptyHands,
+    EmptyHands { operation: &'static str },
     
     /// You can't {operation} a letter! What would that even mean?!
     /// 
     /// operation: ADD, SUB (when 2nd operand is a number), BUMPUP, BUMPDN
-    LetterMath,
+    LetterMath { operation: &'static str },
     
-    /// Bad tile address! Tile with address {addr: u32} does not exist! Where do you think you're going?
-    BadTileAddress,
+    /// Bad tile address! Tile with address {address} does not exist! Where do you think you're going?
+    BadTileAddress { address: i32 },
     
     /// Bad tile address! You can't indirect to a tile with a letter like "{letter}". Only numbers allowed! Where do you think you're going?
-    LetterAddress,
+    LetterAddress { letter: char },
     
     /// Overflow! Each data unit is restricted to values between -999 and 999. That should be enough for anybody.
     Overflow,
+    
+    /// (not in the game) the program ran for too long, and probably never stops.
+    TooManySteps { limit: usize },
 }
 
 impl std::fmt::Display for HRMRuntimeError {
-    fn fmt(&self, _: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> { 
-        todo!()
+    fn fmt(&self, fmtr: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> { 
+        match self {
+            Self::EmptyFloor { operation }
+            => write!(fmtr, "Empty value! You can't {operation} with an empty tile on the floor! Try writing something to that tile first."),
+            Self::EmptyHands { operation }
+            => write!(fmtr, "Empty value! You can't {operation} with empty hands!"),
+            Self::LetterMath { operation }
+            => write!(fmtr, "You can't {operation} a letter! What would that even mean?!"),
+            Self::BadTileAddress { address }
+            => write!(fmtr, "Bad tile address! Tile with address {address} does not exist! Where do you think you're going?"),
+            Self::LetterAddress { letter }
+            => write!(fmtr, "Bad tile address! You can't indirect to a tile with a letter like \"{letter}\". Only numbers allowed! Where do you think you're going?"),
+            Self::Overflow
+            => fmtr.write_str("Overflow! Each data unit is restricted to values between -999 and 999. That should be enough for anybody."),
+            Self::TooManySteps { limit }
+            => write!(fmtr, "Gave up after {limit} steps! Is the program stuck in an infinite loop?"),
+        }
     }
 }
 
 impl std::error::Error for HRMRuntimeError {}
 
 
+/// A [`HRMRuntimeError`], along with the state of the machine when it happened.
+#[derive(Debug, Clone)]
+pub struct HRMRuntimeFault {
+    pub error: HRMRuntimeError,
+    
+    /// the index of the instruction that failed
+    pub program_counter: usize,
+    pub instruction: Instruction,
+    
+    /// the floor tile the instruction was accessing (after following any indirection), if any
+    pub address: Option<usize>,
+    
+    /// what was in hands before the instruction ran
+    pub hands: Option<DataCube>,
+    
+    /// the floor before the instruction ran (only the non-empty tiles are displayed)
+    pub floor: Vec<Option<DataCube>>,
+    
+    /// everything that was put in the outbox before the error
+    pub outbox: Vec<DataCube>,
+    
+    /// how many steps ran before the error
+    pub steps: usize,
+    
+    /// the lines in the original source that the instruction came from (if they're known)
+    pub source_lines: Vec<usize>,
+}
+
+impl std::fmt::Display for HRMRuntimeFault {
+    fn fmt(&self, fmtr: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        writeln!(fmtr, "{}", self.error)?;
+        writeln!(fmtr, "  at instruction {}: {} (after {} steps)", self.program_counter, self.instruction.to_string().trim_end(), self.steps)?;
+        if !self.source_lines.is_empty() {
+            writeln!(fmtr, "  source: {}", format_lines(&self.source_lines))?;
+        }
+        if let Some(address) = self.address {
+            writeln!(fmtr, "  tile: {address}")?;
+        }
+        match &self.hands {
+            Some(cube) => writeln!(fmtr, "  hands: {cube}")?,
+            None => writeln!(fmtr, "  hands: (empty)")?,
+        }
+        write!(fmtr, "  floor ({} tiles):", self.floor.len())?;
+        for (i, tile) in self.floor.iter().enumerate() {
+            if let Some(cube) = tile {
+                write!(fmtr, " [{i}: {cube}]")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for HRMRuntimeFault {}
+
+
 /// Errors that can occur when running tests
-#[allow(dead_code)]
 #[derive(Debug)]
 pub enum HRMTestError {
     /// Not enough stuff in the OUTBOX! Management expected a total of {expected: usize} items, not {actual: usize}!
     NotEnoughOutBox{ actual: usize, expected: usize },
     
     /// Bad outbox! Management expected {test: Datacube}, but you outboxed {actual: Datacube}.
-    BadOutbox{ actual: DataCube, expected: DataCube },
+    /// 
+    /// (`index` is the position in the outbox where things went wrong)
+    BadOutbox{ actual: DataCube, expected: DataCube, index: usize },
+    
+    /// Bad outbox! Management expected nothing, but you outboxed {actual: Datacube}.
+    TooMuchOutBox{ actual: DataCube, index: usize },
+    
+    /// The program crashed before it finished.
+    RuntimeError(Box<HRMRuntimeFault>),
     
     /// Aha! Your solution works with those
     /// specific inputs... but it FAILS on other
     /// possible inputs! Yes, here, I'll give you
     /// some inputs that cause your solution
     /// to fail, so you can see for yourself.
-    SolutionNotRobust,
+    SolutionNotRobust{ inbox: Vec<DataCube>, cause: Box<HRMTestError> },
 }
 
 impl std::fmt::Display for HRMTestError {
@@ -61,10 +141,20 @@ impl std::fmt::Display for HRMTestError {
         match self {
             Self::NotEnoughOutBox { actual, expected }
             => fmtr.write_fmt(format_args!("Not enough stuff in the OUTBOX! Management expected a total of {expected} items, not {actual}!")),
-            Self::BadOutbox { actual, expected }
-            => fmtr.write_fmt(format_args!("Bad outbox! Management expected {expected}, but you outboxed {actual}.")),
-            Self::SolutionNotRobust
-            => fmtr.write_str("\"Aha! Your solution works with those specific inputs... but it FAILS on other possible inputs! Yes, here, I'll give you some inputs that cause your solution to fail, so you can see for yourself.\""),
+            Self::BadOutbox { actual, expected, index }
+            => fmtr.write_fmt(format_args!("Bad outbox! Management expected {expected}, but you outboxed {actual}. (outbox item {})", index + 1)),
+            Self::TooMuchOutBox { actual, index }
+            => fmtr.write_fmt(format_args!("Bad outbox! Management expected nothing, but you outboxed {actual}. (outbox item {})", index + 1)),
+            Self::RuntimeError(fault)
+            => write!(fmtr, "{fault}"),
+            Self::SolutionNotRobust { inbox, cause } => {
+                fmtr.write_str("\"Aha! Your solution works with those specific inputs... but it FAILS on other possible inputs! Yes, here, I'll give you some inputs that cause your solution to fail, so you can see for yourself.\"")?;
+                write!(fmtr, "\n  inbox:")?;
+                for cube in inbox {
+                    write!(fmtr, " {cube}")?;
+                }
+                write!(fmtr, "\n  {cause}")
+            },
         }
     }
 }
@@ -72,12 +162,145 @@ impl std::fmt::Display for HRMTestError {
 impl std::error::Error for HRMTestError {}
 
 
+/// the different things that can go wrong when parsing a program.
 #[derive(Debug)]
 pub enum AsmParseError {
     EmptyFile,
     MissingHeader,
     UnexpectedToken(String),
-    ExpectedToken,
+    /// (the payload describes what was expected, e.g. "a tile address")
+    ExpectedToken(&'static str),
     IntParseError(std::num::ParseIntError),
     UnknownLabel(String)
 }
+
+impl std::fmt::Display for AsmParseError {
+    fn fmt(&self, fmtr: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::EmptyFile => fmtr.write_str("the file is empty"),
+            Self::MissingHeader => fmtr.write_str("missing the `-- HUMAN RESOURCE MACHINE PROGRAM --` header"),
+            Self::UnexpectedToken(token) => write!(fmtr, "unexpected token `{token}`"),
+            Self::ExpectedToken(expected) => write!(fmtr, "expected {expected}"),
+            Self::IntParseError(err) => write!(fmtr, "invalid number ({err})"),
+            Self::UnknownLabel(label) => write!(fmtr, "unknown label `{label}`"),
+        }
+    }
+}
+
+impl std::error::Error for AsmParseError {}
+
+
+/// the different things that can go wrong when parsing a control flow graph from its IR
+/// (see [`crate::optimize::ir`]).
+#[derive(Debug)]
+pub enum IrParseError {
+    /// the same kinds of mistakes as in a program (e.g. an unknown instruction)
+    Asm(AsmParseError),
+    
+    /// two blocks with the same id
+    DuplicateBlock(usize),
+    
+    /// a block that doesn't jump anywhere for some values in hands
+    MissingJump(usize),
+}
+
+impl From<AsmParseError> for IrParseError {
+    fn from(error: AsmParseError) -> Self {
+        Self::Asm(error)
+    }
+}
+
+impl std::fmt::Display for IrParseError {
+    fn fmt(&self, fmtr: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            Self::Asm(error) => write!(fmtr, "{error}"),
+            Self::DuplicateBlock(id) => write!(fmtr, "there's already a block {id}"),
+            Self::MissingJump(id) => write!(fmtr, "block {id} needs to jump somewhere for every value in hands (e.g. with `jump always -> end`)"),
+        }
+    }
+}
+
+impl std::error::Error for IrParseError {}
+
+
+/// an [`AsmParseError`] (or an [`IrParseError`]), along with where it happened in the source file.
+#[derive(Debug)]
+pub struct AsmDiagnostic<E = AsmParseError> {
+    pub error: E,
+    
+    /// the line number of the error (starting from 1)
+    pub line: usize,
+    
+    /// the column the offending text starts at (starting from 1)
+    pub column: usize,
+    
+    /// how many columns the offending text spans
+    pub length: usize,
+    
+    /// the full text of the offending line
+    pub source_line: String,
+}
+
+impl<E> AsmDiagnostic<E> {
+    /// creates a diagnostic for the text in `source_line` at the byte range `span`.
+    pub fn new(error: E, line: usize, source_line: &str, span: std::ops::Range<usize>) -> Self {
+        let start = span.start.min(source_line.len());
+        let end = span.end.min(source_line.len());
+        
+        Self {
+            error,
+            line,
+            column: source_line[..start].chars().count() + 1,
+            length: source_line[start..end].chars().count().max(1),
+            source_line: source_line.to_string(),
+        }
+    }
+}
+
+impl<E: std::fmt::Display> std::fmt::Display for AsmDiagnostic<E> {
+    /// renders the error with the offending line underlined, e.g.
+    /// ```text
+    /// error: unknown label `q`
+    ///  --> line 7, column 14
+    ///   |
+    /// 7 |     JUMP     q
+    ///   |              ^
+    /// ```
+    fn fmt(&self, fmtr: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        let gutter = " ".repeat(self.line.to_string().len());
+        
+        // keep any tabs before the error, so the underline lines up
+        let padding: String = self.source_line.chars()
+            .take(self.column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        
+        writeln!(fmtr, "error: {}", self.error)?;
+        writeln!(fmtr, "{gutter}--> line {}, column {}", self.line, self.column)?;
+        writeln!(fmtr, "{gutter} |")?;
+        writeln!(fmtr, "{} | {}", self.line, self.source_line)?;
+        write!(fmtr, "{gutter} | {padding}{}", "^".repeat(self.length))
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for AsmDiagnostic<E> {}
+
+
+/// all of the errors found while parsing a program.
+#[derive(Debug)]
+pub struct AsmParseErrors<E = AsmParseError>(pub Vec<AsmDiagnostic<E>>);
+
+impl<E: std::fmt::Display> std::fmt::Display for AsmParseErrors<E> {
+    fn fmt(&self, fmtr: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        for diagnostic in self.0.iter() {
+            writeln!(fmtr, "{diagnostic}\n")?;
+        }
+        
+        match self.0.len() {
+            1 => write!(fmtr, "could not parse program due to 1 error"),
+            n => write!(fmtr, "could not parse program due to {n} errors"),
+        }
+    }
+}
+
+impl<E: std::fmt::Debug + std::fmt::Display> std::error::Error for AsmParseErrors<E> {}
@@ -11,51 +11,48 @@ impl Address {
     fn parse(s: &str) -> Result<Self, AsmParseError> {
         // either [integer] or integer
         if let Some(s) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
-            Ok(Address::Indirect(s.parse().map_err(|x| AsmParseError::IntParseError(x))?))
+            Ok(Address::Indirect(s.parse().map_err(AsmParseError::IntParseError)?))
         } else {
-            Ok(Address::Direct(s.parse().map_err(|x| AsmParseError::IntParseError(x))?))
+            Ok(Address::Direct(s.parse().map_err(AsmParseError::IntParseError)?))
         }
     }
     
-    fn raw_address(&self, floor: &Vec<Option<DataCube>>) -> Result<usize, HRMRuntimeError> {
-        match self {
-            Address::Direct(x) => Ok(*x),
+    /// finds the index of the floor tile this address points to.
+    /// 
+    /// `operation` is the name of the instruction accessing the tile, for error messages.
+    pub fn resolve(&self, floor: &[Option<DataCube>], operation: &'static str) -> Result<usize, HRMRuntimeError> {
+        let address = match self {
+            Address::Direct(x) => *x,
             Address::Indirect(x) => {
-                if let Some(tile) = floor.get(*x as usize) {
-                    match tile {
-                        Some(DataCube::Number(x)) => {
-                            TryInto::<usize>::try_into(*x).map_err(|_| HRMRuntimeError::BadTileAddress)
-                        },
-                        Some(DataCube::Letter(_)) => Err(HRMRuntimeError::LetterAddress),
-                        None => Err(HRMRuntimeError::EmptyFloor), // TODO: double check
-                    }
-                } else {
-                    Err(HRMRuntimeError::BadTileAddress)
+                match floor.get(*x) {
+                    Some(Some(DataCube::Number(x))) => {
+                        TryInto::<usize>::try_into(*x).map_err(|_| HRMRuntimeError::BadTileAddress { address: *x as i32 })?
+                    },
+                    Some(Some(DataCube::Letter(letter))) => return Err(HRMRuntimeError::LetterAddress { letter: *letter as char }),
+                    Some(None) => return Err(HRMRuntimeError::EmptyFloor { operation }),
+                    None => return Err(HRMRuntimeError::BadTileAddress { address: *x as i32 }),
                 }
             },
-        }
-    }
-    
-    pub fn follow<'a>(&self, floor: &'a Vec<Option<DataCube>>) -> Result<&'a Option<DataCube>, HRMRuntimeError> {
-        let i = self.raw_address(floor)?;
+        };
         
-        match floor.get(i) {
-            Some(x) => Ok(x),
-            None => Err(HRMRuntimeError::BadTileAddress),
+        if address < floor.len() {
+            Ok(address)
+        } else {
+            Err(HRMRuntimeError::BadTileAddress { address: address as i32 })
         }
     }
-    
-    pub fn follow_mut<'a>(&self, floor: &'a mut Vec<Option<DataCube>>) -> Result<&'a mut Option<DataCube>, HRMRuntimeError> {
-        let i = self.raw_address(floor)?;
-        
-        match floor.get_mut(i) {
-            Some(x) => Ok(x),
-            None => Err(HRMRuntimeError::BadTileAddress),
+}
+
+impl std::fmt::Display for Address {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Address::Direct(x) => write!(f, "{x}"),
+            Address::Indirect(x) => write!(f, "[{x}]"),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction {
     /// #### INBOX: Pick up the next thing from the inbox.
     /// 
@@ -138,18 +135,56 @@ impl Instruction {
                     .unwrap_or(Ok(Self::Outbox))
             },
             
-            "COPYFROM" => Ok(Self::CopyFrom(Address::parse(arg.ok_or(AsmParseError::ExpectedToken)?)?)),
-            "COPYTO" => Ok(Self::CopyTo(Address::parse(arg.ok_or(AsmParseError::ExpectedToken)?)?)),
-            "ADD" => Ok(Self::Add(Address::parse(arg.ok_or(AsmParseError::ExpectedToken)?)?)),
-            "SUB" => Ok(Self::Sub(Address::parse(arg.ok_or(AsmParseError::ExpectedToken)?)?)),
-            "BUMPUP" => Ok(Self::BumpUp(Address::parse(arg.ok_or(AsmParseError::ExpectedToken)?)?)),
-            "BUMPDN" => Ok(Self::BumpDn(Address::parse(arg.ok_or(AsmParseError::ExpectedToken)?)?)),
+            "COPYFROM" => Ok(Self::CopyFrom(Address::parse(arg.ok_or(AsmParseError::ExpectedToken("a tile address"))?)?)),
+            "COPYTO" => Ok(Self::CopyTo(Address::parse(arg.ok_or(AsmParseError::ExpectedToken("a tile address"))?)?)),
+            "ADD" => Ok(Self::Add(Address::parse(arg.ok_or(AsmParseError::ExpectedToken("a tile address"))?)?)),
+            "SUB" => Ok(Self::Sub(Address::parse(arg.ok_or(AsmParseError::ExpectedToken("a tile address"))?)?)),
+            "BUMPUP" => Ok(Self::BumpUp(Address::parse(arg.ok_or(AsmParseError::ExpectedToken("a tile address"))?)?)),
+            "BUMPDN" => Ok(Self::BumpDn(Address::parse(arg.ok_or(AsmParseError::ExpectedToken("a tile address"))?)?)),
             
-            "JUMP" => Ok(Self::Jump(arg.ok_or(AsmParseError::ExpectedToken)?.to_string())),
-            "JUMPZ" => Ok(Self::JumpZ(arg.ok_or(AsmParseError::ExpectedToken)?.to_string())),
-            "JUMPN" => Ok(Self::JumpN(arg.ok_or(AsmParseError::ExpectedToken)?.to_string())),
+            "JUMP" => Ok(Self::Jump(arg.ok_or(AsmParseError::ExpectedToken("a label"))?.to_string())),
+            "JUMPZ" => Ok(Self::JumpZ(arg.ok_or(AsmParseError::ExpectedToken("a label"))?.to_string())),
+            "JUMPN" => Ok(Self::JumpN(arg.ok_or(AsmParseError::ExpectedToken("a label"))?.to_string())),
             
             tok => Err(AsmParseError::UnexpectedToken(tok.to_string())),
         }
     }
+    
+    /// the mnemonic of this instruction, as written in the game's assembly.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Inbox => "INBOX",
+            Self::Outbox => "OUTBOX",
+            Self::CopyFrom(_) => "COPYFROM",
+            Self::CopyTo(_) => "COPYTO",
+            Self::Add(_) => "ADD",
+            Self::Sub(_) => "SUB",
+            Self::BumpUp(_) => "BUMPUP",
+            Self::BumpDn(_) => "BUMPDN",
+            Self::Jump(_) => "JUMP",
+            Self::JumpZ(_) => "JUMPZ",
+            Self::JumpN(_) => "JUMPN",
+        }
+    }
+    
+    /// the argument of this instruction (a tile address or a label), if it has one.
+    pub fn argument(&self) -> Option<String> {
+        match self {
+            Self::Inbox | Self::Outbox => None,
+            Self::CopyFrom(a) | Self::CopyTo(a) | Self::Add(a) | Self::Sub(a) | Self::BumpUp(a) | Self::BumpDn(a)
+                => Some(a.to_string()),
+            Self::Jump(label) | Self::JumpZ(label) | Self::JumpN(label) => Some(label.clone()),
+        }
+    }
+}
+
+impl std::fmt::Display for Instruction {
+    /// formats the instruction the same way the game does, i.e. with the
+    /// mnemonic padded to 8 columns (e.g. `COPYFROM [12]`, `JUMP     a`).
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.argument() {
+            Some(arg) => write!(f, "{:<8} {}", self.name(), arg),
+            None => write!(f, "{:<8}", self.name()),
+        }
+    }
 }
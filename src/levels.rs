@@ -0,0 +1,616 @@
+//! a database of all the puzzle levels in the game.
+//!
+//! (the cutscene levels 5, 15, 18, 27, 33 and 42 aren't included, since there's no program to write for them)
+
+use crate::{datacube::DataCube, rng::Rng};
+
+/// what kind of values can show up in a level's inbox.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueDomain {
+    /// the range of numbers that can show up, if any
+    pub numbers: Option<(i16, i16)>,
+    pub letters: bool,
+}
+
+impl ValueDomain {
+    pub fn contains(&self, cube: &DataCube) -> bool {
+        match cube {
+            DataCube::Number(x) => self.numbers.is_some_and(|(low, high)| (low..=high).contains(x)),
+            DataCube::Letter(_) => self.letters,
+        }
+    }
+    
+    /// a random value from the domain (numbers and letters are equally likely, if it has both).
+    pub fn random(&self, rng: &mut Rng) -> DataCube {
+        match self.numbers {
+            Some((low, high)) if !self.letters || !rng.one_in(2) => rng.number(low, high),
+            _ => rng.letter(),
+        }
+    }
+}
+
+pub struct Level {
+    pub number: u8,
+    pub name: &'static str,
+    pub floor_size: usize,
+    
+    /// the tiles that start out with something on them, as pairs of `(address, value)`
+    pub floor: &'static [(usize, DataCube)],
+    
+    /// the mnemonics of the instructions that can be used in this level
+    pub instructions: &'static [&'static str],
+    
+    /// whether `[n]` addresses can be used in this level
+    pub indirect_addressing: bool,
+    
+    pub inbox_domain: ValueDomain,
+    
+    /// the size challenge (the maximum number of instructions, not counting labels)
+    pub size_challenge: usize,
+    
+    /// the speed challenge (the maximum average number of steps)
+    pub speed_challenge: usize,
+    
+    generator: fn(&mut Rng) -> Vec<DataCube>,
+    oracle: fn(&[DataCube], &[Option<DataCube>]) -> Vec<DataCube>,
+}
+
+impl Level {
+    /// finds the level with the given number.
+    pub fn get(number: u8) -> Option<&'static Level> {
+        LEVELS.iter().find(|level| level.number == number)
+    }
+    
+    /// creates the floor as it is at the start of the level.
+    pub fn initial_floor(&self) -> Vec<Option<DataCube>> {
+        let mut floor = vec![None; self.floor_size];
+        for (address, cube) in self.floor.iter() {
+            floor[*address] = Some(cube.clone());
+        }
+        floor
+    }
+    
+    /// generates a random (valid) inbox for this level.
+    pub fn generate_inbox(&self, rng: &mut Rng) -> Vec<DataCube> {
+        (self.generator)(rng)
+    }
+    
+    /// computes what management expects to be in the outbox for the given inbox.
+    pub fn expected_outbox(&self, inbox: &[DataCube]) -> Vec<DataCube> {
+        (self.oracle)(inbox, &self.initial_floor())
+    }
+}
+
+impl std::fmt::Debug for Level {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "Level({}: {:?})", self.number, self.name)
+    }
+}
+
+
+// instruction sets, as they get unlocked over the course of the game
+const IO: &[&str] = &["INBOX", "OUTBOX"];
+const IO_JUMP: &[&str] = &["INBOX", "OUTBOX", "JUMP"];
+const COPYFROM: &[&str] = &["INBOX", "OUTBOX", "COPYFROM", "JUMP"];
+const COPYTO: &[&str] = &["INBOX", "OUTBOX", "COPYFROM", "COPYTO", "JUMP"];
+const ADD: &[&str] = &["INBOX", "OUTBOX", "COPYFROM", "COPYTO", "ADD", "JUMP"];
+const JUMPZ: &[&str] = &["INBOX", "OUTBOX", "COPYFROM", "COPYTO", "ADD", "JUMP", "JUMPZ"];
+const SUB: &[&str] = &["INBOX", "OUTBOX", "COPYFROM", "COPYTO", "ADD", "SUB", "JUMP", "JUMPZ"];
+const JUMPN: &[&str] = &["INBOX", "OUTBOX", "COPYFROM", "COPYTO", "ADD", "SUB", "JUMP", "JUMPZ", "JUMPN"];
+const ALL: &[&str] = &["INBOX", "OUTBOX", "COPYFROM", "COPYTO", "ADD", "SUB", "BUMPUP", "BUMPDN", "JUMP", "JUMPZ", "JUMPN"];
+
+const SMALL_NUMBERS: ValueDomain = ValueDomain { numbers: Some((-9, 9)), letters: false };
+const SMALL_NUMBERS_AND_LETTERS: ValueDomain = ValueDomain { numbers: Some((-9, 9)), letters: true };
+const DIGITS: ValueDomain = ValueDomain { numbers: Some((0, 9)), letters: false };
+const LETTERS: ValueDomain = ValueDomain { numbers: None, letters: true };
+/// (zero terminated strings of letters)
+const LETTER_STRINGS: ValueDomain = ValueDomain { numbers: Some((0, 0)), letters: true };
+
+const fn letter(c: char) -> DataCube {
+    DataCube::Letter(c as u8)
+}
+
+fn number(x: i32) -> DataCube {
+    DataCube::from_number(x).expect("expected outbox value out of range")
+}
+
+fn value(cube: &DataCube) -> i32 {
+    match cube {
+        DataCube::Number(x) => *x as i32,
+        DataCube::Letter(c) => *c as i32,
+    }
+}
+
+/// a random nonzero number in the range `low..=high`
+fn nonzero(rng: &mut Rng, low: i16, high: i16) -> DataCube {
+    loop {
+        let cube = rng.number(low, high);
+        if cube != DataCube::Number(0) { return cube }
+    }
+}
+
+/// `count` random things from `generate`, one after another
+fn repeat(rng: &mut Rng, count: (i32, i32), mut generate: impl FnMut(&mut Rng) -> DataCube) -> Vec<DataCube> {
+    let count = rng.range(count.0, count.1);
+    (0..count).map(|_| generate(rng)).collect()
+}
+
+/// `count` pairs of random things from `generate`
+fn pairs(rng: &mut Rng, count: (i32, i32), generate: impl FnMut(&mut Rng) -> DataCube) -> Vec<DataCube> {
+    let count = rng.range(count.0, count.1);
+    repeat(rng, (2 * count, 2 * count), generate)
+}
+
+/// `count` zero terminated strings, each with a length in `length` (inclusive)
+fn zero_terminated(rng: &mut Rng, count: (i32, i32), length: (i32, i32), mut generate: impl FnMut(&mut Rng) -> DataCube) -> Vec<DataCube> {
+    let mut inbox = Vec::new();
+    for _ in 0..rng.range(count.0, count.1) {
+        inbox.extend(repeat(rng, length, &mut generate));
+        inbox.push(DataCube::Number(0));
+    }
+    inbox
+}
+
+/// splits a zero terminated inbox into its strings (without the zeros)
+fn strings(inbox: &[DataCube]) -> impl Iterator<Item = &[DataCube]> {
+    let mut strings = inbox.split(|cube| *cube == DataCube::Number(0));
+    strings.next_back(); // (everything after the last zero isn't a full string)
+    strings
+}
+
+/// the items on the floor starting at `address`, up to (but not including) the next zero
+fn floor_string(floor: &[Option<DataCube>], address: usize) -> impl Iterator<Item = DataCube> + '_ {
+    floor[address..].iter()
+        .map_while(|tile| tile.clone().filter(|cube| *cube != DataCube::Number(0)))
+}
+
+fn address(cube: &DataCube) -> usize {
+    value(cube) as usize
+}
+
+
+pub static LEVELS: &[Level] = &[
+    Level {
+        number: 1, name: "Mail Room",
+        floor_size: 0, floor: &[],
+        instructions: IO, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS_AND_LETTERS,
+        size_challenge: 6, speed_challenge: 6,
+        generator: |rng| repeat(rng, (3, 3), |rng| SMALL_NUMBERS_AND_LETTERS.random(rng)),
+        oracle: |inbox, _| inbox.to_vec(),
+    },
+    Level {
+        number: 2, name: "Busy Mail Room",
+        floor_size: 0, floor: &[],
+        instructions: IO_JUMP, indirect_addressing: false,
+        inbox_domain: LETTERS,
+        size_challenge: 3, speed_challenge: 25,
+        generator: |rng| repeat(rng, (6, 12), |rng| LETTERS.random(rng)),
+        oracle: |inbox, _| inbox.to_vec(),
+    },
+    Level {
+        number: 3, name: "Copy Floor",
+        floor_size: 6, floor: &[(0, letter('U')), (1, letter('J')), (2, letter('X')), (3, letter('G')), (4, letter('B')), (5, letter('E'))],
+        instructions: COPYFROM, indirect_addressing: false,
+        inbox_domain: ValueDomain { numbers: Some((-99, 99)), letters: false },
+        size_challenge: 6, speed_challenge: 6,
+        generator: |rng| repeat(rng, (4, 4), |rng| rng.number(-99, 99)),
+        oracle: |_, floor| vec![floor[4].clone().unwrap(), floor[0].clone().unwrap(), floor[3].clone().unwrap()],
+    },
+    Level {
+        number: 4, name: "Scrambler Handler",
+        floor_size: 3, floor: &[],
+        instructions: COPYTO, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS_AND_LETTERS,
+        size_challenge: 7, speed_challenge: 21,
+        generator: |rng| pairs(rng, (3, 5), |rng| SMALL_NUMBERS_AND_LETTERS.random(rng)),
+        oracle: |inbox, _| inbox.chunks_exact(2).flat_map(|pair| [pair[1].clone(), pair[0].clone()]).collect(),
+    },
+    Level {
+        number: 6, name: "Rainy Summer",
+        floor_size: 3, floor: &[],
+        instructions: ADD, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 6, speed_challenge: 24,
+        generator: |rng| pairs(rng, (3, 5), |rng| SMALL_NUMBERS.random(rng)),
+        oracle: |inbox, _| inbox.chunks_exact(2).map(|pair| number(value(&pair[0]) + value(&pair[1]))).collect(),
+    },
+    Level {
+        number: 7, name: "Zero Exterminator",
+        floor_size: 9, floor: &[],
+        instructions: JUMPZ, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS_AND_LETTERS,
+        size_challenge: 4, speed_challenge: 23,
+        generator: |rng| repeat(rng, (6, 10), |rng| if rng.one_in(3) { DataCube::Number(0) } else { SMALL_NUMBERS_AND_LETTERS.random(rng) }),
+        oracle: |inbox, _| inbox.iter().filter(|cube| **cube != DataCube::Number(0)).cloned().collect(),
+    },
+    Level {
+        number: 8, name: "Tripler Room",
+        floor_size: 3, floor: &[],
+        instructions: ADD, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 6, speed_challenge: 24,
+        generator: |rng| repeat(rng, (3, 6), |rng| SMALL_NUMBERS.random(rng)),
+        oracle: |inbox, _| inbox.iter().map(|cube| number(value(cube) * 3)).collect(),
+    },
+    Level {
+        number: 9, name: "Zero Preservation Initiative",
+        floor_size: 9, floor: &[],
+        instructions: JUMPZ, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS_AND_LETTERS,
+        size_challenge: 5, speed_challenge: 25,
+        generator: |rng| repeat(rng, (6, 10), |rng| if rng.one_in(3) { DataCube::Number(0) } else { SMALL_NUMBERS_AND_LETTERS.random(rng) }),
+        oracle: |inbox, _| inbox.iter().filter(|cube| **cube == DataCube::Number(0)).cloned().collect(),
+    },
+    Level {
+        number: 10, name: "Octoplier Suite",
+        floor_size: 5, floor: &[],
+        instructions: JUMPZ, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 9, speed_challenge: 36,
+        generator: |rng| repeat(rng, (3, 6), |rng| SMALL_NUMBERS.random(rng)),
+        oracle: |inbox, _| inbox.iter().map(|cube| number(value(cube) * 8)).collect(),
+    },
+    Level {
+        number: 11, name: "Sub Hallway",
+        floor_size: 3, floor: &[],
+        instructions: SUB, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 10, speed_challenge: 40,
+        generator: |rng| pairs(rng, (3, 5), |rng| SMALL_NUMBERS.random(rng)),
+        oracle: |inbox, _| inbox.chunks_exact(2).flat_map(|pair| {
+            let (a, b) = (value(&pair[0]), value(&pair[1]));
+            [number(b - a), number(a - b)]
+        }).collect(),
+    },
+    Level {
+        number: 12, name: "Tetracontiplier",
+        floor_size: 5, floor: &[],
+        instructions: SUB, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 14, speed_challenge: 56,
+        generator: |rng| repeat(rng, (3, 6), |rng| SMALL_NUMBERS.random(rng)),
+        oracle: |inbox, _| inbox.iter().map(|cube| number(value(cube) * 40)).collect(),
+    },
+    Level {
+        number: 13, name: "Equalization Room",
+        floor_size: 3, floor: &[],
+        instructions: SUB, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 9, speed_challenge: 27,
+        generator: |rng| repeat(rng, (3, 5), |rng| SMALL_NUMBERS.random(rng)).into_iter()
+            .flat_map(|a| [a.clone(), if rng.one_in(2) { a } else { SMALL_NUMBERS.random(rng) }]).collect(),
+        oracle: |inbox, _| inbox.chunks_exact(2).filter(|pair| pair[0] == pair[1]).map(|pair| pair[0].clone()).collect(),
+    },
+    Level {
+        number: 14, name: "Maximization Room",
+        floor_size: 3, floor: &[],
+        instructions: JUMPN, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 10, speed_challenge: 34,
+        generator: |rng| pairs(rng, (3, 5), |rng| SMALL_NUMBERS.random(rng)),
+        oracle: |inbox, _| inbox.chunks_exact(2).map(|pair| number(value(&pair[0]).max(value(&pair[1])))).collect(),
+    },
+    Level {
+        number: 16, name: "Absolute Positivity",
+        floor_size: 3, floor: &[],
+        instructions: JUMPN, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 8, speed_challenge: 36,
+        generator: |rng| repeat(rng, (4, 8), |rng| SMALL_NUMBERS.random(rng)),
+        oracle: |inbox, _| inbox.iter().map(|cube| number(value(cube).abs())).collect(),
+    },
+    Level {
+        number: 17, name: "Exclusive Lounge",
+        floor_size: 6, floor: &[(4, DataCube::Number(0)), (5, DataCube::Number(1))],
+        instructions: JUMPN, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 12, speed_challenge: 28,
+        generator: |rng| pairs(rng, (3, 5), |rng| nonzero(rng, -9, 9)),
+        oracle: |inbox, _| inbox.chunks_exact(2)
+            .map(|pair| number(((value(&pair[0]) < 0) != (value(&pair[1]) < 0)) as i32))
+            .collect(),
+    },
+    Level {
+        number: 19, name: "Countdown",
+        floor_size: 10, floor: &[],
+        instructions: ALL, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 10, speed_challenge: 82,
+        generator: |rng| repeat(rng, (3, 5), |rng| SMALL_NUMBERS.random(rng)),
+        oracle: |inbox, _| inbox.iter().flat_map(|cube| {
+            let x = value(cube);
+            let step = if x < 0 { 1 } else { -1 };
+            std::iter::successors(Some(x), move |&y| (y != 0).then_some(y + step)).map(number)
+        }).collect(),
+    },
+    Level {
+        number: 20, name: "Multiplication Workshop",
+        floor_size: 10, floor: &[(9, DataCube::Number(0))],
+        instructions: ALL, indirect_addressing: false,
+        inbox_domain: DIGITS,
+        size_challenge: 15, speed_challenge: 109,
+        generator: |rng| pairs(rng, (3, 5), |rng| DIGITS.random(rng)),
+        oracle: |inbox, _| inbox.chunks_exact(2).map(|pair| number(value(&pair[0]) * value(&pair[1]))).collect(),
+    },
+    Level {
+        number: 21, name: "Zero Terminated Sum",
+        floor_size: 6, floor: &[(5, DataCube::Number(0))],
+        instructions: ALL, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 10, speed_challenge: 72,
+        generator: |rng| zero_terminated(rng, (2, 4), (0, 4), |rng| nonzero(rng, -9, 9)),
+        oracle: |inbox, _| strings(inbox).map(|string| number(string.iter().map(value).sum())).collect(),
+    },
+    Level {
+        number: 22, name: "Fibonacci Visitor",
+        floor_size: 10, floor: &[(9, DataCube::Number(0))],
+        instructions: ALL, indirect_addressing: false,
+        inbox_domain: ValueDomain { numbers: Some((1, 99)), letters: false },
+        size_challenge: 19, speed_challenge: 156,
+        generator: |rng| repeat(rng, (2, 4), |rng| rng.number(1, 99)),
+        oracle: |inbox, _| inbox.iter().flat_map(|cube| {
+            let limit = value(cube);
+            std::iter::successors(Some((1, 1)), |&(a, b)| Some((b, a + b)))
+                .map(|(a, _)| a)
+                .take_while(move |&a| a <= limit)
+                .map(number)
+        }).collect(),
+    },
+    Level {
+        number: 23, name: "The Littlest Number",
+        floor_size: 10, floor: &[],
+        instructions: ALL, indirect_addressing: false,
+        inbox_domain: ValueDomain { numbers: Some((-99, 99)), letters: false },
+        size_challenge: 13, speed_challenge: 75,
+        generator: |rng| zero_terminated(rng, (2, 4), (1, 5), |rng| nonzero(rng, -99, 99)),
+        oracle: |inbox, _| strings(inbox).map(|string| number(string.iter().map(value).min().unwrap())).collect(),
+    },
+    Level {
+        number: 24, name: "Mod Module",
+        floor_size: 10, floor: &[],
+        instructions: ALL, indirect_addressing: false,
+        inbox_domain: ValueDomain { numbers: Some((0, 99)), letters: false },
+        size_challenge: 12, speed_challenge: 57,
+        generator: |rng| repeat(rng, (2, 4), |rng| rng.number(0, 30)).into_iter().flat_map(|a| [a, rng.number(1, 9)]).collect(),
+        oracle: |inbox, _| inbox.chunks_exact(2).map(|pair| number(value(&pair[0]) % value(&pair[1]))).collect(),
+    },
+    Level {
+        number: 25, name: "Cumulative Countdown",
+        floor_size: 10, floor: &[(5, DataCube::Number(0))],
+        instructions: ALL, indirect_addressing: false,
+        inbox_domain: DIGITS,
+        size_challenge: 12, speed_challenge: 82,
+        generator: |rng| repeat(rng, (3, 5), |rng| DIGITS.random(rng)),
+        oracle: |inbox, _| inbox.iter().map(|cube| number((0..=value(cube)).sum())).collect(),
+    },
+    Level {
+        number: 26, name: "Small Divide",
+        floor_size: 10, floor: &[(9, DataCube::Number(0))],
+        instructions: ALL, indirect_addressing: false,
+        inbox_domain: ValueDomain { numbers: Some((0, 99)), letters: false },
+        size_challenge: 15, speed_challenge: 76,
+        generator: |rng| repeat(rng, (2, 4), |rng| rng.number(0, 30)).into_iter().flat_map(|a| [a, rng.number(1, 9)]).collect(),
+        oracle: |inbox, _| inbox.chunks_exact(2).map(|pair| number(value(&pair[0]) / value(&pair[1]))).collect(),
+    },
+    Level {
+        number: 28, name: "Three Sort",
+        floor_size: 10, floor: &[],
+        instructions: ALL, indirect_addressing: false,
+        inbox_domain: SMALL_NUMBERS,
+        size_challenge: 34, speed_challenge: 78,
+        generator: |rng| repeat(rng, (2, 4), |rng| SMALL_NUMBERS.random(rng)).into_iter().flat_map(|a| [a, SMALL_NUMBERS.random(rng), SMALL_NUMBERS.random(rng)]).collect(),
+        oracle: |inbox, _| inbox.chunks_exact(3).flat_map(|triple| {
+            let mut triple = triple.to_vec();
+            triple.sort_by_key(value);
+            triple
+        }).collect(),
+    },
+    Level {
+        number: 29, name: "Storage Floor",
+        floor_size: 10, floor: &[
+            (0, letter('N')), (1, letter('K')), (2, letter('A')), (3, letter('E')), (4, letter('R')),
+            (5, letter('D')), (6, letter('O')), (7, letter('B')), (8, letter('I')), (9, letter('J')),
+        ],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: DIGITS,
+        size_challenge: 5, speed_challenge: 25,
+        generator: |rng| repeat(rng, (4, 8), |rng| DIGITS.random(rng)),
+        oracle: |inbox, floor| inbox.iter().map(|cube| floor[address(cube)].clone().unwrap()).collect(),
+    },
+    Level {
+        number: 30, name: "String Storage Floor",
+        floor_size: 25, floor: &[
+            (0, letter('B')), (1, letter('U')), (2, letter('G')), (3, DataCube::Number(0)),
+            (4, letter('T')), (5, letter('A')), (6, letter('X')), (7, DataCube::Number(0)),
+            (8, letter('A')), (9, letter('W')), (10, letter('K')), (11, letter('W')), (12, letter('A')), (13, letter('R')), (14, letter('D')), (15, DataCube::Number(0)),
+            (16, letter('B')), (17, letter('R')), (18, letter('A')), (19, letter('I')), (20, letter('N')), (21, DataCube::Number(0)),
+            (22, letter('J')), (23, letter('O')), (24, DataCube::Number(0)),
+        ],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: ValueDomain { numbers: Some((0, 24)), letters: false },
+        size_challenge: 7, speed_challenge: 203,
+        generator: |rng| repeat(rng, (3, 6), |rng| rng.choose(&[0, 1, 2, 4, 5, 8, 11, 13, 16, 18, 22, 23].map(DataCube::Number))),
+        oracle: |inbox, floor| inbox.iter().flat_map(|cube| floor_string(floor, address(cube))).collect(),
+    },
+    Level {
+        number: 31, name: "String Reverse",
+        floor_size: 15, floor: &[(14, DataCube::Number(0))],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: LETTER_STRINGS,
+        size_challenge: 11, speed_challenge: 122,
+        generator: |rng| zero_terminated(rng, (2, 4), (1, 6), Rng::letter),
+        oracle: |inbox, _| strings(inbox).flat_map(|string| string.iter().rev().cloned()).collect(),
+    },
+    Level {
+        number: 32, name: "Inventory Report",
+        floor_size: 15, floor: &[
+            (0, letter('B')), (1, letter('A')), (2, letter('X')), (3, letter('A')), (4, letter('C')),
+            (5, letter('X')), (6, letter('B')), (7, letter('A')), (8, letter('A')), (9, letter('C')),
+            (10, letter('X')), (11, letter('B')), (12, letter('B')), (13, letter('A')), (14, DataCube::Number(0)),
+        ],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: LETTERS,
+        size_challenge: 16, speed_challenge: 393,
+        generator: |rng| repeat(rng, (3, 5), |rng| rng.choose(&[letter('A'), letter('B'), letter('C'), letter('X')])),
+        oracle: |inbox, floor| inbox.iter()
+            .map(|cube| number(floor_string(floor, 0).filter(|tile| tile == cube).count() as i32))
+            .collect(),
+    },
+    Level {
+        number: 34, name: "Vowel Incinerator",
+        floor_size: 10, floor: &[
+            (0, letter('A')), (1, letter('E')), (2, letter('I')), (3, letter('O')), (4, letter('U')), (5, DataCube::Number(0)),
+        ],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: LETTERS,
+        size_challenge: 13, speed_challenge: 323,
+        generator: |rng| repeat(rng, (8, 14), |rng| LETTERS.random(rng)),
+        oracle: |inbox, floor| inbox.iter()
+            .filter(|cube| !floor_string(floor, 0).any(|vowel| vowel == **cube))
+            .cloned()
+            .collect(),
+    },
+    Level {
+        number: 35, name: "Duplicate Removal",
+        floor_size: 15, floor: &[(14, DataCube::Number(0))],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: LETTERS,
+        size_challenge: 17, speed_challenge: 167,
+        generator: |rng| repeat(rng, (8, 14), |rng| DataCube::Letter(b'A' + rng.range(0, 9) as u8)),
+        oracle: |inbox, _| {
+            let mut seen = Vec::new();
+            for cube in inbox {
+                if !seen.contains(cube) { seen.push(cube.clone()) }
+            }
+            seen
+        },
+    },
+    Level {
+        number: 36, name: "Alphabetizer",
+        floor_size: 25, floor: &[(23, DataCube::Number(0)), (24, DataCube::Number(10))],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: LETTER_STRINGS,
+        size_challenge: 39, speed_challenge: 109,
+        generator: |rng| zero_terminated(rng, (2, 2), (1, 8), Rng::letter),
+        oracle: |inbox, _| {
+            let mut words = strings(inbox);
+            let (first, second) = (words.next().unwrap(), words.next().unwrap());
+            let key = |word: &[DataCube]| word.iter().map(value).collect::<Vec<_>>();
+            if key(first) <= key(second) { first.to_vec() } else { second.to_vec() }
+        },
+    },
+    Level {
+        number: 37, name: "Scavenger Chain",
+        floor_size: 25, floor: &[
+            (0, letter('T')), (1, DataCube::Number(4)),
+            (2, letter('A')), (3, DataCube::Number(6)),
+            (4, letter('E')), (5, DataCube::Number(10)),
+            (6, letter('X')), (7, DataCube::Number(-1)),
+            (8, letter('J')), (9, DataCube::Number(2)),
+            (10, letter('A')), (11, DataCube::Number(-1)),
+            (12, letter('K')), (13, DataCube::Number(8)),
+            (14, letter('O')), (15, DataCube::Number(16)),
+            (16, letter('M')), (17, DataCube::Number(-1)),
+            (18, letter('Z')), (19, DataCube::Number(14)),
+        ],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: ValueDomain { numbers: Some((0, 24)), letters: false },
+        size_challenge: 8, speed_challenge: 63,
+        generator: |rng| repeat(rng, (2, 4), |rng| DataCube::Number(2 * rng.range(0, 9) as i16)),
+        oracle: |inbox, floor| inbox.iter().flat_map(|cube| {
+            std::iter::successors(Some(value(cube)), |&pair| (pair >= 0).then(|| value(floor[pair as usize + 1].as_ref().unwrap())))
+                .take_while(|&pair| pair >= 0)
+                .map(|pair| floor[pair as usize].clone().unwrap())
+                .collect::<Vec<_>>()
+        }).collect(),
+    },
+    Level {
+        number: 38, name: "Digit Exploder",
+        floor_size: 12, floor: &[(9, DataCube::Number(0)), (10, DataCube::Number(10)), (11, DataCube::Number(100))],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: ValueDomain { numbers: Some((0, 999)), letters: false },
+        size_challenge: 30, speed_challenge: 165,
+        generator: |rng| repeat(rng, (3, 5), |rng| rng.number(0, 999)),
+        oracle: |inbox, _| inbox.iter()
+            .flat_map(|cube| value(cube).to_string().bytes().map(|digit| number((digit - b'0') as i32)).collect::<Vec<_>>())
+            .collect(),
+    },
+    Level {
+        number: 39, name: "Re-Coordinator",
+        floor_size: 16, floor: &[(14, DataCube::Number(0)), (15, DataCube::Number(4))],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: ValueDomain { numbers: Some((0, 15)), letters: false },
+        size_challenge: 14, speed_challenge: 76,
+        generator: |rng| repeat(rng, (3, 5), |rng| rng.number(0, 15)),
+        oracle: |inbox, _| inbox.iter().flat_map(|cube| [number(value(cube) % 4), number(value(cube) / 4)]).collect(),
+    },
+    Level {
+        number: 40, name: "Prime Factory",
+        floor_size: 25, floor: &[(24, DataCube::Number(0))],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: ValueDomain { numbers: Some((2, 99)), letters: false },
+        size_challenge: 28, speed_challenge: 399,
+        generator: |rng| repeat(rng, (3, 5), |rng| rng.number(2, 99)),
+        oracle: |inbox, _| inbox.iter().flat_map(|cube| {
+            let (mut n, mut factors) = (value(cube), Vec::new());
+            let mut factor = 2;
+            while n > 1 {
+                while n % factor == 0 {
+                    factors.push(number(factor));
+                    n /= factor;
+                }
+                factor += 1;
+            }
+            factors
+        }).collect(),
+    },
+    Level {
+        number: 41, name: "Sorting Room",
+        floor_size: 25, floor: &[(24, DataCube::Number(0))],
+        instructions: ALL, indirect_addressing: true,
+        inbox_domain: ValueDomain { numbers: Some((-99, 99)), letters: true },
+        size_challenge: 34, speed_challenge: 714,
+        generator: |rng| {
+            let mut inbox = Vec::new();
+            for _ in 0..rng.range(2, 4) {
+                // each string is either all letters or all (nonzero) numbers
+                let letters = rng.one_in(2);
+                inbox.extend(repeat(rng, (1, 8), |rng| if letters { rng.letter() } else { nonzero(rng, -99, 99) }));
+                inbox.push(DataCube::Number(0));
+            }
+            inbox
+        },
+        oracle: |inbox, _| strings(inbox).flat_map(|string| {
+            let mut string = string.to_vec();
+            string.sort_by_key(value);
+            string
+        }).collect(),
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn every_level_has_a_working_oracle() {
+        let mut rng = Rng::new(0);
+        for level in LEVELS {
+            assert!(level.floor.iter().all(|(address, _)| *address < level.floor_size), "{level:?}");
+            for _ in 0..20 {
+                let inbox = level.generate_inbox(&mut rng);
+                assert!(inbox.iter().all(|cube| level.inbox_domain.contains(cube)), "{level:?}: {inbox:?}");
+                level.expected_outbox(&inbox);
+            }
+        }
+        
+        let outbox = |number, inbox: &[DataCube]| Level::get(number).unwrap().expected_outbox(inbox);
+        let (a, n) = (DataCube::Letter(b'A'), DataCube::Number);
+        assert_eq!(outbox(4, &[a.clone(), n(3)]), [n(3), a.clone()]);
+        assert_eq!(outbox(14, &[n(3), n(-7)]), [n(3)]);
+        assert_eq!(outbox(19, &[n(3)]), [n(3), n(2), n(1), n(0)]);
+        assert_eq!(outbox(29, &[n(3)]), [DataCube::Letter(b'E')]);
+        assert_eq!(outbox(41, &[n(3), n(-7), n(0), a.clone(), n(0)]), [n(-7), n(3), a]);
+    }
+}
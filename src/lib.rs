@@ -0,0 +1,63 @@
+//! an optimizer for the assembly language in the game "Human Resource Machine".
+//!
+//! the usual way to use this is:
+//! 1. parse a program with [`Program::from_asm`]
+//! 2. turn it into a [`ProgramControlFlowGraph`]
+//! 3. run optimization passes on the graph (either with a [`PassManager`],
+//!    or one at a time with [`ProgramControlFlowGraph::run_optimization_pass`])
+//! 4. turn the graph back into a [`Program`], and emit it with [`Program::to_asm`]
+//!
+//! [`optimize_program`] does steps 2 to 4 in one go:
+//!
+//! ```
+//! use hrm_optimizer::{optimize_program, DataCube, OptimizationGoal, Program};
+//!
+//! let program = Program::from_asm("\
+//! -- HUMAN RESOURCE MACHINE PROGRAM --
+//! a:
+//!     INBOX
+//!     JUMP b
+//! b:
+//!     OUTBOX
+//!     JUMP a
+//! ").unwrap();
+//!
+//! let optimized = optimize_program(&program, OptimizationGoal::Speed);
+//! assert!(optimized.instructions.len() < program.instructions.len());
+//!
+//! let inbox = vec![DataCube::Number(1), DataCube::Letter(b'A')];
+//! let (_steps, outbox) = optimized.simulate(inbox.clone()).unwrap();
+//! assert_eq!(outbox, inbox);
+//! ```
+
+pub mod errors;
+pub mod datacube;
+pub mod instruction;
+pub mod program;
+
+pub mod rng;
+pub mod levels;
+pub mod check;
+pub mod equivalence;
+
+pub mod optimize;
+
+pub use datacube::DataCube;
+pub use instruction::{Address, Instruction};
+pub use program::Program;
+pub use optimize::{
+    block_layout::OptimizationGoal,
+    control_flow_graph::{Optimization, ProgramControlFlowGraph},
+    pass_manager::PassManager,
+};
+
+/// runs every optimization pass on a program, and returns the optimized program
+/// (which keeps the original's initial floor).
+pub fn optimize_program(program: &Program, goal: OptimizationGoal) -> Program {
+    let mut graph = ProgramControlFlowGraph::new(program);
+    PassManager::preset(goal).run(&mut graph);
+    
+    let mut optimized: Program = (&graph).into();
+    optimized.initial_floor = program.initial_floor.clone();
+    optimized
+}
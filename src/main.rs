@@ -1,98 +1,283 @@
-use datacube::DataCube;
+use std::process::ExitCode;
 
-use crate::optimize::control_flow_graph::ProgramControlFlowGraph;
+use hrm_optimizer::{
+    check,
+    equivalence::{self, ErrorBehavior, PassVerifier},
+    optimize::{pass_manager::PassManager, remarks::RemarkKind},
+    program::{self, Program},
+    rng::Rng,
+    DataCube, Instruction, ProgramControlFlowGraph,
+};
 
-mod errors;
-mod datacube;
-mod instruction;
-mod program;
+use crate::cli::{ArgsError, Command, InboxSource, Options, RemarksFormat};
 
-mod optimize;
+mod cli;
 
-fn main() -> std::process::ExitCode {
-    let argv = std::env::args().collect::<Vec<_>>();
-    if argv.len() != 2 {
-        eprintln!("Usage: {} <file path>", argv[0]);
-        return std::process::ExitCode::FAILURE;
-    }
-    
-    let asm_file_contents = std::fs::read_to_string(&argv[1]).expect("Failed to read file");
-    let mut program = program::Program::from_asm(&asm_file_contents).unwrap();
-    program.initial_floor = vec![None; 16];
-    program.initial_floor[15] = Some(DataCube::from_number(4).unwrap());
-    program.initial_floor[14] = Some(DataCube::from_number(0).unwrap());
-    
-    // for (i, inst) in program.instructions.iter().enumerate() {
-    //     println!("{i}. {inst:?}");
-    // }
-    // println!("{:?}", program.jump_label_lines);
+/// why a command didn't succeed, which decides the exit code.
+enum Failure {
+    /// the program did something wrong (exit code 1)
+    Program(String),
     
-    let mut cfg = ProgramControlFlowGraph::new(&program);
+    /// the command line didn't make sense (exit code 2)
+    Usage(String),
     
-    // optimization loop
-    loop {
-        use optimize::block_optimizations::*;
-        use optimize::local_optimizations::*;
-        
-        if cfg.run_optimization_pass(local_optimization(simplify_outgoing_jumps)) {
-            println!("simplify_outgoing_jumps"); continue
-        } else if cfg.run_optimization_pass(remove_dead_blocks) {
-            println!("remove_dead_blocks"); continue 
-        } else if cfg.run_optimization_pass(combine_sequential_blocks) {
-            println!("combine_sequential_blocks"); continue
-        } else if cfg.run_optimization_pass(remove_empty_blocks) {
-            println!("remove_empty_blocks"); continue
-        } else if cfg.run_optimization_pass(local_optimization(peephole_optimizations)) {
-            println!("peephole_optimizations"); continue
+    /// the program couldn't be read or parsed, or the output couldn't be written (exit code 3)
+    Input(String),
+}
+
+impl Failure {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            Self::Program(_) => ExitCode::from(1),
+            Self::Usage(_) => ExitCode::from(2),
+            Self::Input(_) => ExitCode::from(3),
         }
-        
-        cfg.relabel_blocks();
-        
-        break;
     }
     
-    for block in cfg.blocks.iter() {
-        println!("Block {:?}:", block.id.0);
-        
-        match &block.incoming_jumps[..] {
-            [] => if block.id.0 != 0 { println!("  (DEAD BLOCK)") },
-            [jumps @ ..] => {
-                println!("  Incoming jumps:");
-                for (id, flag) in jumps {
-                    println!("    -> Block {:?} ({:?})", id.0, flag);
-                }
-                println!();
-            },
+    fn message(&self) -> &str {
+        match self {
+            Self::Program(message) | Self::Usage(message) | Self::Input(message) => message,
         }
-        
-        for inst in block.instructions.iter() {
-            println!("  {inst:?}");
+    }
+}
+
+fn main() -> ExitCode {
+    let options = match cli::parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(ArgsError::Help) => {
+            print!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        },
+        Err(error) => {
+            eprint!("{error}");
+            return ExitCode::from(2);
+        },
+    };
+    
+    let result = match options.command {
+        Command::Optimize => optimize(&options),
+        Command::Run => run(&options),
+        Command::Check => check(&options),
+        Command::Cfg => cfg(&options),
+        Command::Fmt => fmt(&options),
+        Command::Stats => stats(&options),
+    };
+    
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            eprintln!("{}", failure.message());
+            failure.exit_code()
+        },
+    }
+}
+
+/// reads the program from the input file (or stdin), and sets up its floor.
+fn load_program(options: &Options) -> Result<Program, Failure> {
+    let asm = match &options.input {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|error| Failure::Input(format!("Failed to read {}: {error}", path.display())))?,
+        None => std::io::read_to_string(std::io::stdin())
+            .map_err(|error| Failure::Input(format!("Failed to read stdin: {error}")))?,
+    };
+    
+    let mut program = Program::from_asm(&asm).map_err(|errors| Failure::Input(errors.to_string()))?;
+    program.initial_floor = options.initial_floor();
+    Ok(program)
+}
+
+/// runs the optimizer on a program, logging (and verifying) each pass as asked.
+fn optimize_program(options: &Options, program: &Program) -> Result<Program, Failure> {
+    let mut cfg = ProgramControlFlowGraph::new(program);
+    
+    let verifier = options.verify_passes.then(|| {
+        let inboxes = equivalence::generate_inboxes(&mut Rng::new(options.seed), 200);
+        PassVerifier::new(program, inboxes, ErrorBehavior::Undefined)
+    });
+    
+    let mut passes = match &options.passes {
+        Some(names) => PassManager::from_names(names, options.goal).expect("the passes are checked when parsing the arguments"),
+        None => PassManager::preset(options.goal),
+    };
+    
+    // (the snapshots are numbered, so that they sort in the order the passes ran in)
+    let mut snapshots = 0;
+    let mut write_snapshot = |pass: &str, cfg: &ProgramControlFlowGraph| match &options.dot_passes {
+        Some(dir) => {
+            let path = dir.join(format!("{snapshots:03}-{pass}.dot"));
+            snapshots += 1;
+            std::fs::create_dir_all(dir)
+                .and_then(|_| std::fs::write(&path, cfg.to_dot(pass)))
+                .map_err(|error| Failure::Input(format!("Failed to write {}: {error}", path.display())))
+        },
+        None => Ok(()),
+    };
+    write_snapshot("input", &cfg)?;
+    
+    let statistics = passes.run_with(&mut cfg, |pass, cfg| {
+        if options.verbosity >= 2 {
+            eprintln!("{pass}");
         }
         
-        println!("  Outgoing jumps:");
-        for (id, flag) in block.outgoing_jumps.iter() {
-            println!("    -> Block {:?} ({:?})", id.0, flag);
+        write_snapshot(pass, cfg)?;
+        match &verifier {
+            Some(verifier) => verifier.verify(pass, cfg).map_err(|error| Failure::Program(error.to_string())),
+            None => Ok(()),
         }
-        println!();
-        
-        println!();
+    })?;
+    
+    if options.verbosity >= 2 {
+        eprintln!("{statistics}");
+    }
+    if !statistics.converged && options.verbosity >= 1 {
+        eprintln!("warning: the passes kept changing the program, so the optimizer gave up after {} of them", statistics.total());
+    }
+    
+    match options.remarks {
+        Some(RemarksFormat::Text) => eprint!("{}", cfg.remarks),
+        Some(RemarksFormat::Json) => eprint!("{}", cfg.remarks.to_json()),
+        // (warnings are worth seeing even without asking for every remark)
+        None if options.verbosity >= 1 => {
+            for remark in cfg.remarks.0.iter().filter(|remark| remark.kind == RemarkKind::Warning) {
+                eprintln!("{remark}");
+            }
+        },
+        None => {},
+    }
+    
+    if options.verbosity >= 3 {
+        eprint!("{}", cfg.dump());
+    }
+    
+    let mut optimized: Program = (&cfg).into();
+    optimized.initial_floor = program.initial_floor.clone();
+    Ok(optimized)
+}
+
+/// the program the command should work on (optimized first, if `--optimized` was given).
+fn target_program(options: &Options) -> Result<Program, Failure> {
+    let program = load_program(options)?;
+    if options.optimized {
+        optimize_program(options, &program)
+    } else {
+        Ok(program)
+    }
+}
+
+fn write_output(options: &Options, contents: &str) -> Result<(), Failure> {
+    match &options.output {
+        Some(path) => std::fs::write(path, contents)
+            .map_err(|error| Failure::Input(format!("Failed to write {}: {error}", path.display()))),
+        None => {
+            print!("{contents}");
+            Ok(())
+        },
+    }
+}
+
+fn format_cubes(cubes: &[DataCube]) -> String {
+    cubes.iter().map(|cube| cube.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+fn optimize(options: &Options) -> Result<(), Failure> {
+    let program = load_program(options)?;
+    let optimized = optimize_program(options, &program)?;
+    
+    if options.verbosity >= 2 {
+        eprintln!("{} instructions -> {} instructions", program.instructions.len(), optimized.instructions.len());
+    }
+    
+    write_output(options, &if options.annotate { optimized.to_asm_annotated() } else { optimized.to_asm() })
+}
+
+fn run(options: &Options) -> Result<(), Failure> {
+    let program = target_program(options)?;
+    
+    let inbox = match (&options.inbox, options.level) {
+        (Some(InboxSource::Values(inbox)), _) => inbox.clone(),
+        (Some(InboxSource::File(path)), _) => {
+            let values = std::fs::read_to_string(path)
+                .map_err(|error| Failure::Input(format!("Failed to read {}: {error}", path.display())))?;
+            cli::parse_inbox(&values).map_err(|error| Failure::Input(format!("{}: {error}", path.display())))?
+        },
+        (None, Some(level)) => level.generate_inbox(&mut Rng::new(options.seed)),
+        (None, None) => return Err(Failure::Usage("`run` needs an inbox (use `--inbox`, `--inbox-file` or `--level`)".to_string())),
+    };
+    
+    if options.verbosity >= 2 {
+        eprintln!("inbox: {}", format_cubes(&inbox));
+    }
+    
+    match program.simulate_with(inbox, program.initial_floor.clone(), Some(program::STEP_LIMIT)) {
+        Ok((steps, outbox)) => {
+            println!("{}", format_cubes(&outbox));
+            if options.verbosity >= 1 {
+                eprintln!("({steps} steps)");
+            }
+            Ok(())
+        },
+        Err(fault) => {
+            println!("{}", format_cubes(&fault.outbox));
+            Err(Failure::Program(fault.to_string()))
+        },
+    }
+}
+
+fn check(options: &Options) -> Result<(), Failure> {
+    let program = target_program(options)?;
+    let level = options.level.expect("the level is checked for when parsing the arguments");
+    
+    let score = check::score(&program, level, options.runs, &mut Rng::new(options.seed))
+        .map_err(|error| Failure::Program(error.to_string()))?;
+    
+    if options.verbosity >= 1 {
+        println!("level {}: {}", level.number, level.name);
+        println!("{score}");
+    }
+    
+    Ok(())
+}
+
+fn cfg(options: &Options) -> Result<(), Failure> {
+    let program = target_program(options)?;
+    let cfg = ProgramControlFlowGraph::new(&program);
+    if options.dot {
+        print!("{}", cfg.to_dot("cfg"));
+    } else if options.ir {
+        print!("{}", cfg.to_ir());
+    } else {
+        print!("{}", cfg.dump());
+    }
+    Ok(())
+}
+
+fn fmt(options: &Options) -> Result<(), Failure> {
+    let program = load_program(options)?;
+    write_output(options, &program.to_asm())
+}
+
+fn stats(options: &Options) -> Result<(), Failure> {
+    let program = load_program(options)?;
+    let optimized = optimize_program(options, &program)?;
+    
+    let jumps = |program: &Program| program.instructions.iter()
+        .filter(|instruction| matches!(instruction, Instruction::Jump(_) | Instruction::JumpZ(_) | Instruction::JumpN(_)))
+        .count();
+    let blocks = |program: &Program| ProgramControlFlowGraph::new(program).blocks.len();
+    
+    println!("{:<14} {:>10} {:>10}", "", "original", "optimized");
+    println!("{:<14} {:>10} {:>10}", "instructions", program.instructions.len(), optimized.instructions.len());
+    println!("{:<14} {:>10} {:>10}", "jumps", jumps(&program), jumps(&optimized));
+    println!("{:<14} {:>10} {:>10}", "blocks", blocks(&program), blocks(&optimized));
+    
+    // (the speed can only be measured on a level, since it depends on the inbox)
+    if let Some(level) = options.level {
+        let speed = |program: &Program| match check::score(program, level, options.runs, &mut Rng::new(options.seed)) {
+            Ok(score) => format!("{:.2}", score.mean()),
+            Err(_) => "(fails)".to_string(),
+        };
+        println!("{:<14} {:>10} {:>10}", "average steps", speed(&program), speed(&optimized));
     }
     
-    let _: program::Program = (&cfg).into();
-    
-    // (NOTE: average perf: 182 steps)
-    println!("{:?}", program.simulate(vec![
-        DataCube::from_char('A').unwrap(),
-        DataCube::from_char('D').unwrap(),
-        DataCube::from_char('E').unwrap(),
-        DataCube::from_char('C').unwrap(),
-        DataCube::from_char('A').unwrap(),
-        DataCube::from_char('D').unwrap(),
-        DataCube::from_char('E').unwrap(),
-        DataCube::from_char('D').unwrap(),
-        DataCube::from_char('B').unwrap(),
-        DataCube::from_char('E').unwrap(),
-    ]).unwrap());
-    
-    return std::process::ExitCode::SUCCESS;
+    Ok(())
 }
@@ -1,4 +1,4 @@
-use crate::instruction::Instruction;
+use crate::{instruction::Instruction, program::SourceLines};
 
 use super::jump_flag::JumpFlag;
 
@@ -12,5 +12,76 @@ pub struct BasicBlock {
     pub instructions: Vec<Instruction>,
     pub outgoing_jumps: Vec<(BasicBlockId, JumpFlag)>,
     pub incoming_jumps: Vec<(BasicBlockId, JumpFlag)>,
+    
+    /// the `COMMENT n` lines in this block, as pairs of `(index, n)`, where the
+    /// comment is placed right before the instruction at `index`.
+    pub comments: Vec<(usize, usize)>,
+    
+    /// the lines in the source file that each instruction came from.
+    /// (the methods below keep this in sync with `instructions`)
+    pub source_lines: Vec<SourceLines>,
+    
+    /// the lines in the source file that the jumps at the end of the block came from
+    pub jump_lines: SourceLines,
+}
+
+impl BasicBlock {
+    /// removes the instruction at `index`, moving any comments before it onto the next instruction.
+    pub fn remove_instruction(&mut self, index: usize) -> Instruction {
+        for (i, _) in self.comments.iter_mut() {
+            if *i > index { *i -= 1 }
+        }
+        if index < self.source_lines.len() {
+            self.source_lines.remove(index);
+        }
+        self.instructions.remove(index)
+    }
+    
+    /// removes the instruction at `index`, for when the instruction at `into` now does its job too
+    /// (so that the source lines of both end up on the one that's left).
+    pub fn merge_instruction_into(&mut self, index: usize, into: usize) -> Instruction {
+        if let Some(lines) = self.source_lines.get(index).cloned() {
+            if let Some(into) = self.source_lines.get_mut(into) {
+                into.extend(lines);
+            }
+        }
+        self.remove_instruction(index)
+    }
+    
+    /// the source lines of the instructions in `range` (that are known), in order.
+    pub fn lines(&self, range: std::ops::Range<usize>) -> Vec<usize> {
+        let lines: SourceLines = self.source_lines.get(range).unwrap_or(&[]).iter().flatten().copied().collect();
+        lines.into_iter().collect()
+    }
+    
+    /// the outgoing jumps, with each flag narrowed down to the cases where that jump is actually
+    /// the one that gets taken (since an earlier jump might take some of its cases first).
+    /// 
+    /// jumps that can never be taken are left out.
+    pub fn effective_outgoing_jumps(&self) -> Vec<(BasicBlockId, JumpFlag)> {
+        let mut taken = JumpFlag::Never;
+        let mut jumps = Vec::new();
+        
+        for (target, flag) in self.outgoing_jumps.iter() {
+            let effective = *flag & !taken;
+            taken |= *flag;
+            
+            if effective != JumpFlag::Never {
+                jumps.push((target.clone(), effective));
+            }
+        }
+        
+        jumps
+    }
+    
+    /// moves all the instructions (and comments) from `other` onto the end of this block.
+    pub fn append_instructions(&mut self, other: &mut BasicBlock) {
+        let offset = self.instructions.len();
+        self.comments.extend(other.comments.drain(..).map(|(i, comment)| (i + offset, comment)));
+        self.source_lines.resize(offset, SourceLines::new());
+        other.source_lines.resize(other.instructions.len(), SourceLines::new());
+        self.source_lines.append(&mut other.source_lines);
+        self.instructions.append(&mut other.instructions);
+    }
 }
 
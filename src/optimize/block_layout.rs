@@ -0,0 +1,262 @@
+//! choosing the order that the blocks of a program get emitted in.
+//!
+//! the order doesn't change what a program does, but it does change which jumps can be
+//! left out (a block can just fall through to the next one), and therefore both the size
+//! of the program and how many steps it takes.
+
+use std::collections::HashMap;
+
+use crate::instruction::Instruction;
+
+use super::{
+    basic_blocks::{BasicBlock, BasicBlockId},
+    control_flow_graph::{Optimization, ProgramControlFlowGraph},
+    jump_flag::JumpFlag,
+};
+
+/// what the optimizer should try to make better, when it has to choose.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OptimizationGoal {
+    /// the fewest instructions (the size challenge)
+    Size,
+    
+    /// the fewest steps on average (the speed challenge)
+    Speed,
+}
+
+/// how long inboxes are assumed to be when estimating how often blocks run.
+const EXPECTED_INBOX_LENGTH: f64 = 10.0;
+
+/// the chance that a block runs all the way to its jumps, instead of
+/// ending the program at an `INBOX` (when the inbox is empty).
+fn continue_probability(block: &BasicBlock) -> f64 {
+    let inboxes = block.instructions.iter().filter(|inst| matches!(inst, Instruction::Inbox)).count();
+    (1.0 - 1.0 / EXPECTED_INBOX_LENGTH).powi(inboxes as i32)
+}
+
+/// the chance that the accumulator matches a flag, assuming zero,
+/// negative and positive (or letter) values are all equally likely.
+fn flag_probability(flag: JumpFlag) -> f64 {
+    (flag as u8).count_ones() as f64 / 3.0
+}
+
+/// roughly how many times each block runs in a single run of the program.
+///
+/// there's no way to know this for sure without knowing the inbox, so this assumes
+/// every conditional jump is as likely to go one way as any other, and that each
+/// `INBOX` has a small chance of ending the program.
+pub fn estimate_block_frequencies(graph: &ProgramControlFlowGraph) -> HashMap<BasicBlockId, f64> {
+    let index: HashMap<_, _> = graph.blocks.iter().enumerate()
+        .map(|(i, block)| (block.id.clone(), i))
+        .collect();
+    
+    let jumps: Vec<_> = graph.blocks.iter()
+        .map(|block| block.effective_outgoing_jumps().into_iter()
+            .filter_map(|(target, flag)| Some((*index.get(&target)?, flag_probability(flag))))
+            .collect::<Vec<_>>())
+        .collect();
+    
+    let mut frequencies = vec![0.0; graph.blocks.len()];
+    
+    // NOTE: this is just solving a markov chain by iterating it. a loop that never
+    //       ends (and has no INBOX in it) would make this grow forever, so it's capped.
+    for _ in 0..1000 {
+        let mut next = vec![0.0; graph.blocks.len()];
+        if let Some(&entry) = index.get(&BasicBlockId(0)) {
+            next[entry] = 1.0;
+        }
+        
+        for (i, block) in graph.blocks.iter().enumerate() {
+            let reaches_end = frequencies[i] * continue_probability(block);
+            for &(j, probability) in jumps[i].iter() {
+                next[j] += reaches_end * probability;
+            }
+        }
+        
+        let converged = next.iter().zip(frequencies.iter()).all(|(a, b)| (a - b).abs() < 1e-9);
+        frequencies = next;
+        if converged { break }
+    }
+    
+    graph.blocks.iter().map(|block| block.id.clone()).zip(frequencies).collect()
+}
+
+/// the average number of jump instructions that run when leaving a block through `jumps`.
+fn expected_jumps(jumps: &[(Instruction, BasicBlockId)]) -> f64 {
+    let mut remaining = JumpFlag::Always;
+    let mut expected = 0.0;
+    
+    for (i, (jump, _)) in jumps.iter().enumerate() {
+        let taken = remaining & match jump {
+            Instruction::JumpZ(_) => JumpFlag::IfZero,
+            Instruction::JumpN(_) => JumpFlag::IfNegative,
+            _ => JumpFlag::Always,
+        };
+        expected += (i + 1) as f64 * flag_probability(taken);
+        remaining &= !taken;
+    }
+    
+    // (falling through to the next block still means running every jump)
+    expected + jumps.len() as f64 * flag_probability(remaining)
+}
+
+/// what a block costs, depending on whether it gets to fall through to the next block.
+struct BlockCost {
+    /// the block that the positive case goes to (which is the only one that can be fallen through to),
+    /// or `None` if it goes to the end of the program.
+    fallthrough: Option<usize>,
+    
+    /// `(size, steps)` if the block does fall through
+    with_fallthrough: (f64, f64),
+    
+    /// `(size, steps)` if it doesn't
+    without_fallthrough: (f64, f64),
+}
+
+impl BlockCost {
+    fn new(graph: &ProgramControlFlowGraph, block: &BasicBlock, frequency: f64) -> Self {
+        let target = block.effective_outgoing_jumps().into_iter()
+            .find(|(_, flag)| flag.contains(JumpFlag::IfPositive))
+            .map(|(target, _)| target)
+            .expect("block has no outgoing jump for positive values");
+        
+        let fallthrough = graph.blocks.iter().position(|b| b.id == target);
+        
+        // (the last block falls through to the end of the program)
+        let (with, without) = match fallthrough {
+            Some(_) => (graph.lower_outgoing_jumps(block, Some(&target)), graph.lower_outgoing_jumps(block, None)),
+            None => (graph.lower_outgoing_jumps(block, None), graph.lower_outgoing_jumps(block, Some(&block.id))),
+        };
+        
+        let frequency = frequency * continue_probability(block);
+        let cost = |jumps: &[(Instruction, BasicBlockId)]| (jumps.len() as f64, frequency * expected_jumps(jumps));
+        
+        Self {
+            fallthrough,
+            with_fallthrough: cost(&with),
+            without_fallthrough: cost(&without),
+        }
+    }
+}
+
+/// the cost of laying out the blocks in the given order, as `(primary, tiebreaker)`.
+fn layout_cost(graph: &ProgramControlFlowGraph, order: &[usize], costs: &[BlockCost], goal: OptimizationGoal) -> (f64, f64) {
+    // if the entry block isn't first, the program has to start by jumping to it
+    let entry_jump = if graph.blocks[order[0]].id.0 != 0 { 1.0 } else { 0.0 };
+    
+    let mut size = entry_jump;
+    let mut steps = entry_jump;
+    
+    for (k, &i) in order.iter().enumerate() {
+        let cost = &costs[i];
+        let (block_size, block_steps) = if order.get(k + 1).copied() == cost.fallthrough {
+            cost.with_fallthrough
+        } else {
+            cost.without_fallthrough
+        };
+        
+        size += block_size;
+        steps += block_steps;
+    }
+    
+    match goal {
+        OptimizationGoal::Size => (size, steps),
+        OptimizationGoal::Speed => (steps, size),
+    }
+}
+
+fn is_better(a: (f64, f64), b: (f64, f64)) -> bool {
+    const EPSILON: f64 = 1e-9;
+    a.0 < b.0 - EPSILON || ((a.0 - b.0).abs() < EPSILON && a.1 < b.1 - EPSILON)
+}
+
+/// the best order for the blocks that a local search can find (as indices into `graph.blocks`),
+/// along with its cost and the cost of the original order, as `(primary, tiebreaker)`.
+fn best_layout(graph: &ProgramControlFlowGraph, goal: OptimizationGoal) -> (Vec<usize>, (f64, f64), (f64, f64)) {
+    let frequencies = estimate_block_frequencies(graph);
+    let costs: Vec<BlockCost> = graph.blocks.iter()
+        .map(|block| BlockCost::new(graph, block, frequencies[&block.id]))
+        .collect();
+    
+    let mut order: Vec<usize> = (0..graph.blocks.len()).collect();
+    let mut best = layout_cost(graph, &order, &costs, goal);
+    let original = best;
+    
+    // local search: keep moving runs of blocks somewhere else, as long as that helps.
+    // (starting from the original order means that it's kept unless there's a reason not to)
+    'search: loop {
+        for start in 0..order.len() {
+            for end in start + 1..=order.len() {
+                for destination in 0..=order.len() - (end - start) {
+                    if destination == start { continue }
+                    
+                    let mut candidate = order.clone();
+                    let run: Vec<_> = candidate.drain(start..end).collect();
+                    candidate.splice(destination..destination, run);
+                    
+                    let cost = layout_cost(graph, &candidate, &costs, goal);
+                    if is_better(cost, best) {
+                        order = candidate;
+                        best = cost;
+                        continue 'search;
+                    }
+                }
+            }
+        }
+        
+        break;
+    }
+    
+    (order, best, original)
+}
+
+/// the cost of the graph once its blocks are laid out as well as [`layout_blocks`] can, as `(primary, tiebreaker)`.
+///
+/// (only the jumps are counted, since the order doesn't change any of the other instructions)
+pub(crate) fn best_layout_cost(graph: &ProgramControlFlowGraph, goal: OptimizationGoal) -> (f64, f64) {
+    best_layout(graph, goal).1
+}
+
+/// the jump instructions at the end of each block (in the same order as `graph.blocks`),
+/// once the blocks are laid out as well as [`layout_blocks`] can.
+pub(crate) fn best_layout_jumps(graph: &ProgramControlFlowGraph, goal: OptimizationGoal) -> Vec<Vec<(Instruction, BasicBlockId)>> {
+    let (order, _, _) = best_layout(graph, goal);
+    
+    let mut jumps = vec![Vec::new(); graph.blocks.len()];
+    for (k, &i) in order.iter().enumerate() {
+        let next = order.get(k + 1).map(|&j| &graph.blocks[j].id);
+        jumps[i] = graph.lower_outgoing_jumps(&graph.blocks[i], next);
+    }
+    jumps
+}
+
+/// reorders the blocks to need as few jump instructions as possible, either in the
+/// program itself or in an average run of it (depending on `goal`).
+///
+/// only the `Always` (or rather, the positive) edge out of a block can ever be a fallthrough,
+/// since there's no way to jump when the accumulator is positive. the entry block doesn't
+/// have to come first, in which case the program starts with a `JUMP` to it.
+pub fn layout_blocks(goal: OptimizationGoal) -> impl Optimization {
+    move |graph: &mut ProgramControlFlowGraph| {
+        if graph.blocks.len() < 2 { return false }
+        
+        let (order, best, original) = best_layout(graph, goal);
+        
+        if order.iter().enumerate().all(|(i, &j)| i == j) {
+            return false;
+        }
+        
+        let ids = |order: &[usize]| order.iter().map(|&i| graph.blocks[i].id.0.to_string()).collect::<Vec<_>>().join(" ");
+        let savings = match goal {
+            OptimizationGoal::Size => format!("{} fewer instruction(s)", original.0 - best.0),
+            OptimizationGoal::Speed => format!("about {:.2} fewer steps per run", original.0 - best.0),
+        };
+        let message = format!("reordered the blocks from [{}] to [{}], for {savings}", ids(&(0..order.len()).collect::<Vec<_>>()), ids(&order));
+        graph.remarks.transformation(Vec::new(), message);
+        
+        let mut blocks: Vec<Option<BasicBlock>> = std::mem::take(&mut graph.blocks).into_iter().map(Some).collect();
+        graph.blocks = order.iter().map(|&i| blocks[i].take().unwrap()).collect();
+        
+        true
+    }
+}
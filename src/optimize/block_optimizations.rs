@@ -1,10 +1,24 @@
-use crate::optimize::jump_flag::JumpFlag;
+use std::collections::HashSet;
 
-use super::control_flow_graph::ProgramControlFlowGraph;
+use crate::{instruction::Instruction, optimize::jump_flag::JumpFlag, program::SourceLines};
+
+use super::{
+    basic_blocks::{BasicBlock, BasicBlockId},
+    block_layout::OptimizationGoal,
+    control_flow_graph::{Optimization, ProgramControlFlowGraph},
+    remarks::describe,
+};
 
 pub fn remove_dead_blocks(graph: &mut ProgramControlFlowGraph) -> bool {
     let old_len = graph.blocks.len();
-    graph.blocks.retain(|block| block.id.0 == 0 || !block.incoming_jumps.is_empty());
+    let remarks = &mut graph.remarks;
+    graph.blocks.retain(|block| {
+        let alive = block.id.0 == 0 || !block.incoming_jumps.is_empty();
+        if !alive {
+            remarks.transformation(block.lines(0..block.instructions.len()), format!("removed block {}, which can never be reached", block.id.0));
+        }
+        alive
+    });
     old_len != graph.blocks.len()
 }
 
@@ -20,9 +34,15 @@ pub fn combine_sequential_blocks(graph: &mut ProgramControlFlowGraph) -> bool {
         let block2 = _blocks_after.get_mut(offset).unwrap();
         
         match (&block1.outgoing_jumps[..], &block2.incoming_jumps[..]) {
-            ([(b, JumpFlag::Always)], [(_, JumpFlag::Always)]) if /* *a == block1.id && */ *b == block2.id => {
-                block1.instructions.extend(block2.instructions.drain(..));
+            // NOTE: the entry block can't be merged into anything, since the program starts there
+            ([(b, JumpFlag::Always)], [(_, JumpFlag::Always)]) if /* *a == block1.id && */ *b == block2.id && block2.id.0 != 0 => {
+                graph.remarks.transformation(block2.lines(0..block2.instructions.len()), format!(
+                    "merged block {} into block {}, since it always runs right after it", block2.id.0, block1.id.0,
+                ));
+                block1.append_instructions(block2);
                 block1.outgoing_jumps = block2.outgoing_jumps.clone();
+                // (the jump from block1 to block2 is gone, so only block2's jumps are left)
+                block1.jump_lines = std::mem::take(&mut block2.jump_lines);
                 to_remove.push(i+offset+1);
                 offset += 1;
             }
@@ -31,50 +51,290 @@ pub fn combine_sequential_blocks(graph: &mut ProgramControlFlowGraph) -> bool {
     }
     
     for &i in to_remove.iter().rev() {
+        // keep any comments in the same place in the program
+        let comments = std::mem::take(&mut graph.blocks[i].comments);
+        if let Some(previous_block) = i.checked_sub(1).map(|j| &mut graph.blocks[j]) {
+            let end = previous_block.instructions.len();
+            previous_block.comments.extend(comments.into_iter().map(|(_, comment)| (end, comment)));
+        }
+        
         graph.blocks.remove(i);
     }
     
     !to_remove.is_empty()
 }
 
-/// NOTE: soundness depends on `simplify_outgoing_jumps` and 
-///       then `refresh_incoming_jumps` being run before this.
+/// NOTE: soundness depends on `refresh_incoming_jumps` being run before this.
 pub fn remove_empty_blocks(graph: &mut ProgramControlFlowGraph) -> bool {
-    let mut to_remove: Vec<usize> = vec![];
+    let mut modified = false;
     
-    for (i, block) in graph.blocks.iter().enumerate() {
-        if block.instructions.is_empty() {
-            to_remove.push(i);
-        }
-    }
+    // NOTE: the blocks are removed one at a time, since removing one changes the jumps into the
+    //       others (e.g. in a loop of empty blocks, the last one left ends up jumping to itself).
+    //
+    // NOTE: the entry block can't be removed, since nothing jumps to it
+    //       and the program would start at whatever block comes next.
+    //       an empty block that jumps to itself is an infinite loop, and has nowhere else to go.
+    let removable = |graph: &ProgramControlFlowGraph| graph.blocks.iter().position(|block| {
+        block.instructions.is_empty() && block.id.0 != 0 && !block.outgoing_jumps.iter().any(|(id, _)| *id == block.id)
+    });
     
-    for &i in to_remove.iter().rev() {
+    while let Some(i) = removable(graph) {
         // can't just remove the block, because it might have incoming jumps
         let current_block_id = graph.blocks[i].id.clone();
         let incoming_jumps = graph.blocks[i].incoming_jumps.clone();
         let outgoing_jumps = graph.blocks[i].outgoing_jumps.clone();
+        let jump_lines = graph.blocks[i].jump_lines.clone();
+        
+        graph.remarks.transformation(Vec::new(), format!(
+            "removed empty block {}, so the {} jump(s) to it go straight to where it goes instead", current_block_id.0, incoming_jumps.len(),
+        ));
         
         for (id, flag) in incoming_jumps {
             let block_idx = graph.blocks.iter().position(|block| block.id == id).expect("invalid block id");
             let block = &mut graph.blocks[block_idx];
             
-            // replace the incoming jump with all the jumps from the block we're removing
-            // NOTE: since we know that all the jumps in the preceeding block commute (we
-            //       just ran `simplify_outgoing_jumps`), we can just append them all to
-            //       the end of its outgoing jumps.
+            // replace the incoming jump with all the jumps from the block we're removing, in the same
+            // place (so any values in hands that it doesn't take still go on to the jumps after it)
             let jump_pos = block.outgoing_jumps.iter()
                 .position(|(id2, _)| *id2 == current_block_id)
                 .expect("incoming jump not found");
             
-            let (_block_id, _out_flag) = block.outgoing_jumps.remove(jump_pos);
+            let replacement = outgoing_jumps.iter().map(|(id_out, flag_out)| (id_out.clone(), *flag_out & flag));
+            let (_block_id, _out_flag) = block.outgoing_jumps.splice(jump_pos..=jump_pos, replacement).next().unwrap();
             debug_assert_eq!(_block_id, current_block_id);
             debug_assert_eq!(flag, _out_flag);
             
-            block.outgoing_jumps.extend(outgoing_jumps.iter().map(|(id_out, flag_out)| (id_out.clone(), *flag_out & flag)));
+            block.jump_lines.extend(jump_lines.iter().copied());
+        }
+        
+        // keep any comments in the same place in the program
+        let comments = std::mem::take(&mut graph.blocks[i].comments);
+        if let Some(previous_block) = i.checked_sub(1).map(|j| &mut graph.blocks[j]) {
+            let end = previous_block.instructions.len();
+            previous_block.comments.extend(comments.into_iter().map(|(_, comment)| (end, comment)));
         }
         
         graph.blocks.remove(i);
+        graph.refresh_incoming_jumps();
+        modified = true;
     }
     
-    !to_remove.is_empty()
+    modified
+}
+
+/// moves the instructions that every block jumping to a block ends with into the start of that block
+/// (i.e. tail merging), when those blocks are the only way into it and they always jump there.
+///
+/// every path into the block still runs the instruction exactly once, so this is never slower.
+///
+/// NOTE: soundness depends on `refresh_incoming_jumps` being run before this.
+pub fn merge_tails(graph: &mut ProgramControlFlowGraph) -> bool {
+    let mut modified = false;
+    
+    for i in 0..graph.blocks.len() {
+        let target = graph.blocks[i].id.clone();
+        
+        // NOTE: nothing can be moved into the entry block, since the program also starts there
+        //       without running anything before it.
+        let mut predecessors: Vec<usize> = graph.blocks[i].incoming_jumps.iter()
+            .filter_map(|(id, _)| graph.blocks.iter().position(|block| block.id == *id))
+            .collect();
+        predecessors.sort();
+        predecessors.dedup();
+        if target.0 == 0 || predecessors.len() < 2 || predecessors.contains(&i) { continue }
+        
+        loop {
+            let tails: Vec<_> = predecessors.iter().map(|&j| {
+                let block = &graph.blocks[j];
+                block.instructions.last().filter(|_| block.effective_outgoing_jumps() == [(target.clone(), JumpFlag::Always)])
+            }).collect();
+            let Some(Some(tail)) = tails.first() else { break };
+            if !tails.iter().all(|other| *other == Some(tail)) { break }
+            let tail = (*tail).clone();
+            
+            let mut lines = SourceLines::new();
+            for &j in predecessors.iter() {
+                let block = &mut graph.blocks[j];
+                lines.extend(block.lines(block.instructions.len() - 1..block.instructions.len()));
+                block.remove_instruction(block.instructions.len() - 1);
+            }
+            
+            graph.remarks.transformation(lines.iter().copied().collect(), format!(
+                "moved the {} at the end of blocks {} into block {}, since they're the only way into it",
+                describe(&tail), predecessors.iter().map(|&j| graph.blocks[j].id.0.to_string()).collect::<Vec<_>>().join(", "), target.0,
+            ));
+            
+            // (comments at the very start stay in front of the moved instruction, next to the label)
+            let block = &mut graph.blocks[i];
+            for (index, _) in block.comments.iter_mut().filter(|(index, _)| *index > 0) {
+                *index += 1;
+            }
+            block.source_lines.resize(block.instructions.len(), SourceLines::new());
+            block.source_lines.insert(0, lines);
+            block.instructions.insert(0, tail);
+            modified = true;
+        }
+    }
+    
+    modified
+}
+
+/// the most instructions a block can have to be copied by [`thread_jumps`] (when optimizing for speed).
+const THREADING_LIMIT: usize = 3;
+
+/// makes jumps skip tests whose outcome is already known from the jump itself.
+///
+/// a block that only has `COPYTO`s doesn't change what's in hands, so whatever a jump into it
+/// knows about hands (e.g. that it's not zero, since it's the other side of a `JUMPZ`) is still
+/// true at its jumps. when that's enough to know which of them gets taken, the jump goes to a copy
+/// of the block that always goes there instead. (the original stays for any other jumps into it)
+///
+/// copying a block makes the program bigger, so when optimizing for size this only happens when
+/// the jump is the only way into it, and otherwise only for blocks of up to [`THREADING_LIMIT`] instructions.
+pub fn thread_jumps(goal: OptimizationGoal) -> impl Optimization {
+    move |graph: &mut ProgramControlFlowGraph| {
+        let ids: HashSet<_> = graph.blocks.iter().map(|block| block.id.clone()).collect();
+        
+        for (i, block) in graph.blocks.iter().enumerate() {
+            for (k, (target, flag)) in block.effective_outgoing_jumps().into_iter().enumerate() {
+                let Some(j) = graph.blocks.iter().position(|block| block.id == target) else { continue };
+                let next = &graph.blocks[j];
+                if i == j || !next.instructions.iter().all(|instruction| matches!(instruction, Instruction::CopyTo(_))) {
+                    continue;
+                }
+                
+                // (a block with a single jump doesn't test anything)
+                let jumps = next.effective_outgoing_jumps();
+                let taken: Vec<_> = jumps.iter().filter(|(_, next_flag)| *next_flag & flag != JumpFlag::Never).collect();
+                let ([(destination, _)], true) = (&taken[..], jumps.len() > 1) else { continue };
+                
+                let only_way_in = next.id.0 != 0 && next.incoming_jumps.len() == 1;
+                let profitable = only_way_in || match goal {
+                    OptimizationGoal::Size => false,
+                    OptimizationGoal::Speed => next.instructions.len() <= THREADING_LIMIT,
+                };
+                if !profitable { continue }
+                
+                // (new blocks need an id that isn't used yet, including by the jumps to the end of the program)
+                let id = graph.blocks.iter()
+                    .flat_map(|block| std::iter::once(block.id.0).chain(block.outgoing_jumps.iter().map(|(id, _)| id.0)))
+                    .max().unwrap_or(0) + 1;
+                
+                let destination_name = match ids.contains(destination) {
+                    true => format!("block {}", destination.0),
+                    false => "the end of the program".to_string(),
+                };
+                let mut lines = next.lines(0..next.instructions.len());
+                lines.extend(next.jump_lines.iter().copied());
+                graph.remarks.transformation(lines, format!(
+                    "made the jump from block {} to block {} go straight to {destination_name} (through a copy of block {} as block {id}), since hands are always {flag} when it's taken",
+                    block.id.0, next.id.0, next.id.0,
+                ));
+                
+                let copy = BasicBlock {
+                    id: BasicBlockId(id),
+                    instructions: next.instructions.clone(),
+                    outgoing_jumps: vec![(destination.clone(), JumpFlag::Always)],
+                    incoming_jumps: Vec::new(),
+                    comments: Vec::new(),
+                    source_lines: next.source_lines.clone(),
+                    jump_lines: next.jump_lines.clone(),
+                };
+                
+                // (the effective jumps do the same thing as the original ones, so they can replace them)
+                let mut outgoing_jumps = block.effective_outgoing_jumps();
+                outgoing_jumps[k].0 = BasicBlockId(id);
+                graph.blocks[i].outgoing_jumps = outgoing_jumps;
+                graph.blocks.insert(i + 1, copy);
+                
+                // (the incoming jumps are out of date now, so the pipeline has to refresh them before the next one)
+                return true;
+            }
+        }
+        
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{instruction::Instruction, program::Program};
+    
+    use super::*;
+    
+    #[test]
+    fn empty_infinite_loops_are_kept() {
+        // (an empty block that jumps to itself has nowhere else to go, so it can't be removed)
+        for program in ["INBOX\n    OUTBOX\nl:\n    JUMP     l\n", "INBOX\na:\n    JUMP     b\nb:\n    JUMP     a\n"] {
+            let program = Program::from_asm(&format!("-- HUMAN RESOURCE MACHINE PROGRAM --\n\n    {program}")).unwrap();
+            let mut graph = ProgramControlFlowGraph::new(&program);
+            while graph.run_optimization_pass(remove_empty_blocks) {}
+            
+            let optimized: Program = (&graph).into();
+            assert!(optimized.instructions.contains(&Instruction::Jump("a".to_string())), "{}", optimized.to_asm());
+        }
+    }
+    
+    #[test]
+    fn shared_endings_move_into_the_block() {
+        // both ways into `b` end with `COPYFROM 0, COPYTO 3`, so both instructions move into it
+        // (but not the ones before them, which are different)
+        let program = Program::from_asm("\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+    INBOX
+    JUMPZ    a
+    COPYTO   1
+    COPYFROM 0
+    COPYTO   3
+    JUMP     b
+a:
+    OUTBOX
+    COPYFROM 0
+    COPYTO   3
+b:
+    BUMPUP   3
+    OUTBOX
+").unwrap();
+        let mut graph = ProgramControlFlowGraph::new(&program);
+        while graph.run_optimization_pass(merge_tails) {}
+        
+        let optimized: Program = (&graph).into();
+        assert_eq!(optimized.to_asm(), "\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+    INBOX   
+    JUMPZ    a
+    COPYTO   1
+    JUMP     b
+a:
+    OUTBOX  
+b:
+    COPYFROM 0
+    COPYTO   3
+    BUMPUP   3
+    OUTBOX  
+
+");
+    }
+    
+    #[test]
+    fn tails_stay_before_conditional_jumps() {
+        // the first block ends with the same COPYTO as the second one, but it doesn't always
+        // jump to `a`, so the COPYTO can't be moved there
+        let program = Program::from_asm("\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+    INBOX
+    COPYTO   1
+    JUMPN    a
+    COPYFROM 0
+    COPYTO   1
+a:
+    COPYFROM 1
+    OUTBOX
+").unwrap();
+        let mut graph = ProgramControlFlowGraph::new(&program);
+        assert!(!graph.run_optimization_pass(merge_tails));
+    }
 }
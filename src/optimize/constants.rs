@@ -0,0 +1,262 @@
+//! constant and copy propagation over the floor tiles and hands.
+//!
+//! this keeps track of which tiles (and hands) hold a value that's known at compile time, starting
+//! from the initial floor, and which tiles are known to hold the same value as hands right now.
+//! an indirect address whose pointer tile is known gets treated like the direct address it points to.
+
+use crate::{
+    datacube::DataCube,
+    instruction::{Address, Instruction},
+};
+
+use super::{
+    basic_blocks::{BasicBlock, BasicBlockId},
+    control_flow_graph::ProgramControlFlowGraph,
+    dataflow::{self, DataflowAnalysis, DataflowOptions, DataflowResult, Direction, Lattice},
+    jump_flag::JumpFlag,
+    liveness::{Liveness, TileSet},
+};
+
+/// what's known about the value in hands, or on a tile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    /// there's nothing there
+    Empty,
+    Known(DataCube),
+    
+    /// it could be anything (including nothing)
+    Unknown,
+}
+
+impl Value {
+    fn join(&mut self, other: &Self) {
+        if self != other {
+            *self = Value::Unknown;
+        }
+    }
+}
+
+/// the sign of a value, as far as the jumps are concerned.
+/// (letters are neither zero nor negative, so they count as positive)
+pub fn sign(cube: &DataCube) -> JumpFlag {
+    match cube {
+        DataCube::Number(0) => JumpFlag::IfZero,
+        DataCube::Number(n) if *n < 0 => JumpFlag::IfNegative,
+        _ => JumpFlag::IfPositive,
+    }
+}
+
+/// everything that's known at some point in the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub hands: Value,
+    pub floor: Vec<Value>,
+    
+    /// the tiles that hold the same value as hands
+    pub copies: TileSet,
+}
+
+impl State {
+    /// the tile that an address points to, if it's known.
+    pub fn resolve(&self, address: &Address) -> Option<usize> {
+        match address {
+            Address::Direct(a) => Some(*a).filter(|a| *a < self.floor.len()),
+            Address::Indirect(a) => match self.floor.get(*a)? {
+                Value::Known(DataCube::Number(n)) => usize::try_from(*n).ok().filter(|n| *n < self.floor.len()),
+                _ => None,
+            },
+        }
+    }
+    
+    /// what's on the tile that an address points to.
+    pub fn tile(&self, address: &Address) -> &Value {
+        match self.resolve(address) {
+            Some(tile) => &self.floor[tile],
+            None => &Value::Unknown,
+        }
+    }
+    
+    /// true if the tile that an address points to is known to hold the same value as hands.
+    pub fn holds_hands(&self, address: &Address) -> bool {
+        match (self.resolve(address), &self.hands) {
+            (Some(tile), _) if self.copies.contains(tile) => true,
+            (Some(tile), Value::Known(hands)) => self.floor[tile] == Value::Known(hands.clone()),
+            _ => false,
+        }
+    }
+    
+    /// updates the state to what it is after `instruction` runs.
+    ///
+    /// (anything that would be a runtime error makes the result unknown, since the program stops there anyway)
+    pub fn transfer(&mut self, instruction: &Instruction) {
+        use Instruction::*;
+        
+        match instruction {
+            Inbox => {
+                self.hands = Value::Unknown;
+                self.copies.clear();
+            },
+            Outbox => {
+                self.hands = Value::Empty;
+                self.copies.clear();
+            },
+            CopyFrom(address) => {
+                self.hands = self.tile(address).clone();
+                self.copies.clear();
+                if let Some(tile) = self.resolve(address) {
+                    self.copies.insert(tile);
+                }
+            },
+            CopyTo(address) => match self.resolve(address) {
+                Some(tile) => {
+                    self.floor[tile] = self.hands.clone();
+                    self.copies.insert(tile);
+                },
+                // (any tile might get overwritten, but the ones that already hold hands still do)
+                None => {
+                    for tile in self.floor.iter_mut() {
+                        tile.join(&self.hands);
+                    }
+                },
+            },
+            Add(address) | Sub(address) => {
+                let result = match (&self.hands, self.tile(address)) {
+                    (Value::Known(a), Value::Known(b)) => match instruction {
+                        Add(_) => add(a, b),
+                        _ => sub(a, b),
+                    },
+                    _ => None,
+                };
+                self.hands = result.map(Value::Known).unwrap_or(Value::Unknown);
+                self.copies.clear();
+            },
+            BumpUp(address) | BumpDn(address) => {
+                self.copies.clear();
+                match self.resolve(address) {
+                    Some(tile) => {
+                        let one = DataCube::Number(1);
+                        let result = match (&self.floor[tile], instruction) {
+                            (Value::Known(value), BumpUp(_)) => add(value, &one),
+                            (Value::Known(value), _) => sub(value, &one),
+                            _ => None,
+                        };
+                        self.floor[tile] = result.map(Value::Known).unwrap_or(Value::Unknown);
+                        self.hands = self.floor[tile].clone();
+                        self.copies.insert(tile);
+                    },
+                    None => {
+                        self.floor.fill(Value::Unknown);
+                        self.hands = Value::Unknown;
+                    },
+                }
+            },
+            Jump(_) | JumpZ(_) | JumpN(_) => {},
+        }
+    }
+}
+
+fn add(a: &DataCube, b: &DataCube) -> Option<DataCube> {
+    match (a, b) {
+        (DataCube::Number(a), DataCube::Number(b)) => DataCube::from_number(a + b).ok(),
+        _ => None,
+    }
+}
+
+fn sub(a: &DataCube, b: &DataCube) -> Option<DataCube> {
+    match (a, b) {
+        (DataCube::Number(a), DataCube::Number(b)) => DataCube::from_number(a - b).ok(),
+        (DataCube::Letter(a), DataCube::Letter(b)) => DataCube::from_number(*a as i16 - *b as i16).ok(),
+        _ => None,
+    }
+}
+
+/// `None` means the code is never reached.
+impl Lattice for Option<State> {
+    fn join(&mut self, other: &Self) {
+        match (self.as_mut(), other) {
+            (_, None) => {},
+            (None, Some(other)) => *self = Some(other.clone()),
+            (Some(state), Some(other)) => {
+                state.hands.join(&other.hands);
+                for (a, b) in state.floor.iter_mut().zip(other.floor.iter()) {
+                    a.join(b);
+                }
+                state.copies.intersect(&other.copies);
+            },
+        }
+    }
+}
+
+/// the analysis itself, for [`dataflow::solve`].
+struct ConstantPropagation {
+    floor_size: usize,
+}
+
+impl DataflowAnalysis for ConstantPropagation {
+    type Fact = Option<State>;
+    
+    const DIRECTION: Direction = Direction::Forward;
+    
+    /// (if there's no initial floor, nothing is known about it)
+    fn boundary(&self, graph: &ProgramControlFlowGraph) -> Option<State> {
+        let floor = (0..self.floor_size)
+            .map(|tile| match graph.initial_floor.get(tile) {
+                _ if graph.initial_floor.is_empty() => Value::Unknown,
+                Some(Some(cube)) => Value::Known(cube.clone()),
+                _ => Value::Empty,
+            })
+            .collect();
+        
+        Some(State { hands: Value::Empty, floor, copies: TileSet::empty(self.floor_size) })
+    }
+    
+    fn bottom(&self, _graph: &ProgramControlFlowGraph) -> Option<State> {
+        None
+    }
+    
+    fn transfer(&self, fact: &mut Option<State>, instruction: &Instruction) {
+        if let Some(state) = fact {
+            state.transfer(instruction);
+        }
+    }
+    
+    /// a jump that's taken for some signs can't be taken if hands have a different sign,
+    /// and a jump that's only taken for zero means hands are zero.
+    fn transfer_edge(&self, fact: &mut Option<State>, flag: JumpFlag) {
+        let Some(state) = fact else { return };
+        match &state.hands {
+            Value::Known(cube) if !flag.contains(sign(cube)) => *fact = None,
+            Value::Unknown if flag == JumpFlag::IfZero => state.hands = Value::Known(DataCube::Number(0)),
+            _ => {},
+        }
+    }
+}
+
+/// what's known at the start and end of every block in a control flow graph.
+#[derive(Debug, Clone)]
+pub struct KnownValues {
+    result: DataflowResult<Option<State>>,
+    floor_size: usize,
+}
+
+impl KnownValues {
+    pub fn compute(graph: &ProgramControlFlowGraph) -> Self {
+        let floor_size = Liveness::floor_size(graph);
+        
+        // (the lattice is finite, so there's no need for an iteration limit)
+        let options = DataflowOptions { iteration_limit: None, ..Default::default() };
+        
+        Self { result: dataflow::solve(&ConstantPropagation { floor_size }, graph, options), floor_size }
+    }
+    
+    /// what's known right before each instruction in a block, and at the end of it.
+    /// (`None` if the block is never reached)
+    pub fn within_block(&self, block: &BasicBlock) -> Vec<Option<State>> {
+        self.result.within_block(&ConstantPropagation { floor_size: self.floor_size }, block)
+    }
+    
+    /// what's known at the end of a block, before its jumps.
+    pub fn block_end(&self, block: &BasicBlockId) -> &Option<State> {
+        self.result.block_end(block)
+    }
+}
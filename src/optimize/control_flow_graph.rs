@@ -1,7 +1,7 @@
 use crate::{
-    program::Program,
+    program::{format_lines, Comment, Program, Drawing, SourceLines},
     optimize::{
-        basic_blocks::{BasicBlockId, BasicBlock}, jump_flag::JumpFlag
+        basic_blocks::{BasicBlockId, BasicBlock}, jump_flag::JumpFlag, remarks::Remarks, structure::Structure,
     },
     instruction::Instruction,
     datacube::DataCube
@@ -21,9 +21,23 @@ impl<T: FnMut(&mut ProgramControlFlowGraph) -> bool> Optimization for T {
 }
 
 
+/// a program, split up into basic blocks with the jumps between them.
+///
+/// the entry block always has id 0 (but doesn't have to be the first block), and a jump
+/// to an id that isn't in `blocks` is a jump to the end of the program.
+#[derive(Debug)]
 pub struct ProgramControlFlowGraph {
     pub initial_floor: Vec<Option<DataCube>>,
+    
+    /// the blocks, in the order they get emitted in
     pub blocks: Vec<BasicBlock>,
+    pub drawings: Vec<Drawing>,
+    
+    /// everything the passes have changed so far, and why
+    pub remarks: Remarks,
+    
+    /// the dominators and loops of the graph, if they've been computed since it last changed
+    pub(crate) structure: std::cell::OnceCell<Structure>,
 }
 
 impl ProgramControlFlowGraph {
@@ -34,7 +48,7 @@ impl ProgramControlFlowGraph {
         // find leaders, s.t. each pair in leader_indices is the start and end of a block
         let mut leader_indices = vec![0];
         
-        for (i, inst) in program.instructions.iter().enumerate().skip(1) {
+        for (i, inst) in program.instructions.iter().enumerate() {
             if let Jump(_) | JumpN(_) | JumpZ(_) = inst {
                 if !matches!(program.instructions.get(i + 1), Some(Jump(_) | JumpN(_) | JumpZ(_))) {
                     leader_indices.push(i + 1);
@@ -63,7 +77,7 @@ impl ProgramControlFlowGraph {
             let end = b;
             
             // advance b backwards to ignore jumps
-            while let Some(Jump(_) | JumpN(_) | JumpZ(_)) = program.instructions.get(b-1) {
+            while b > a && matches!(program.instructions[b-1], Jump(_) | JumpN(_) | JumpZ(_)) {
                 b -= 1;
             }
             
@@ -91,17 +105,36 @@ impl ProgramControlFlowGraph {
                 jumps.push((BasicBlockId(i + 1), JumpFlag::Always));
             }
             
+            // comments between the jumps at the end of the block (or at the very end of the program)
+            // just get put at the end of the block, along with the ones right before the next block's label
+            let is_last_block = end == program.instructions.len();
+            let belongs_here = |comment: &Comment| match comment.line {
+                line if line == a && comment.before_label && i > 0 => false,
+                line if line == end && comment.before_label => true,
+                line => (a..end).contains(&line) || (is_last_block && line >= end),
+            };
+            let comments = program.comments.iter()
+                .filter(|comment| belongs_here(comment))
+                .map(|comment| (comment.line.min(b) - a, comment.index))
+                .collect();
+            
             BasicBlock {
                 id: BasicBlockId(i),
                 instructions: program.instructions[a..b].to_vec(),
+                source_lines: (a..b).map(|i| program.source_lines.get(i).cloned().unwrap_or_default()).collect(),
+                jump_lines: (b..end).flat_map(|i| program.source_lines.get(i).cloned().unwrap_or_default()).collect(),
                 outgoing_jumps: jumps,
                 incoming_jumps: vec![],
+                comments,
             }
         }).collect();
         
         let mut result = Self {
             initial_floor: program.initial_floor.clone(),
             blocks,
+            drawings: program.drawings.clone(),
+            remarks: Remarks::default(),
+            structure: Default::default(),
         };
         
         result.refresh_incoming_jumps();
@@ -112,11 +145,12 @@ impl ProgramControlFlowGraph {
     /// update all incoming jumps for each block.
     /// 
     /// it is MANDATORY to call this function after modifying the outgoing jumps of any block.
-    fn refresh_incoming_jumps(&mut self) {
-        let mut block_ids = Vec::new();
-        for block in self.blocks.iter_mut() {
+    pub(crate) fn refresh_incoming_jumps(&mut self) {
+        // NOTE: the blocks aren't necessarily in order of their ids (e.g. after they get laid out)
+        let mut block_ids = std::collections::HashMap::new();
+        for (i, block) in self.blocks.iter_mut().enumerate() {
             block.incoming_jumps.clear();
-            block_ids.push(block.id.clone());
+            block_ids.insert(block.id.clone(), i);
         }
         let block_ids = block_ids;
         
@@ -131,9 +165,9 @@ impl ProgramControlFlowGraph {
                     continue;
                 }
                 
-                let jmp_idx = match block_ids.binary_search(out_jmp_id) {
-                    Ok(i) => i,
-                    Err(_) => continue,
+                let jmp_idx = match block_ids.get(out_jmp_id) {
+                    Some(&i) => i,
+                    None => continue,
                 };
                 
                 let target_block = if jmp_idx < i {
@@ -149,17 +183,86 @@ impl ProgramControlFlowGraph {
         }
     }
     
+    /// runs an optimization pass on the graph, and returns true if it changed anything.
     pub fn run_optimization_pass(&mut self, mut optimizer: impl Optimization) -> bool {
         let result = optimizer.optimize(self);
-        if result { self.refresh_incoming_jumps(); }
+        if result {
+            self.refresh_incoming_jumps();
+            self.invalidate_structure();
+        }
         result
     }
     
+    /// the dominators, post-dominators and loops of the graph (see [`Structure`]), which are
+    /// only computed the first time they're needed after the graph changes.
+    /// 
+    /// NOTE: a pass that uses this after changing the graph itself has to call
+    ///       `invalidate_structure` first, or it gets the structure from before the change.
+    pub fn structure(&self) -> &Structure {
+        self.structure.get_or_init(|| Structure::compute(self))
+    }
+    
+    /// forgets the cached [`structure`](Self::structure), since the graph has changed.
+    pub fn invalidate_structure(&mut self) {
+        self.structure.take();
+    }
+    
+    /// turns the outgoing jumps of a block into the cheapest equivalent sequence
+    /// of jump instructions.
+    /// 
+    /// the labels of the returned instructions are left empty, and the block each
+    /// one jumps to is returned alongside it. if the block can just fall through
+    /// to `next_block`, the final unconditional jump is omitted.
+    pub(crate) fn lower_outgoing_jumps(&self, block: &BasicBlock, next_block: Option<&BasicBlockId>) -> Vec<(Instruction, BasicBlockId)> {
+        // find where the block ends up for each possible value of the accumulator.
+        // NOTE: the outgoing jumps are checked in order, so this works whether or
+        //       not `simplify_outgoing_jumps` has been run on the block.
+        let target_if = |sign: JumpFlag| block.outgoing_jumps.iter()
+            .position(|(_, flag)| flag.contains(sign))
+            .unwrap_or_else(|| panic!("block {:?} has no outgoing jump for {sign:?}", block.id));
+        
+        let zero = target_if(JumpFlag::IfZero);
+        let negative = target_if(JumpFlag::IfNegative);
+        // (letters also end up here, since they are neither zero nor negative)
+        let positive = target_if(JumpFlag::IfPositive);
+        
+        // there's no "JUMPP" instruction, so the positive case always has to be the one that
+        // falls through to the end of the block. this means the zero and negative cases only
+        // need a conditional jump if they go somewhere else. (those two commute, so just keep
+        // them in the same order as the original jumps)
+        let mut conditional_jumps = Vec::new();
+        if zero != positive {
+            conditional_jumps.push((zero, Instruction::JumpZ(String::new())));
+        }
+        if negative != positive {
+            conditional_jumps.push((negative, Instruction::JumpN(String::new())));
+        }
+        conditional_jumps.sort_by_key(|(i, _)| *i);
+        
+        let mut jumps: Vec<_> = conditional_jumps.into_iter()
+            .map(|(i, jump)| (jump, block.outgoing_jumps[i].0.clone()))
+            .collect();
+        
+        // jumping to the next block (or to the end of the program from the last block) is redundant
+        let target = &block.outgoing_jumps[positive].0;
+        let falls_through = match next_block {
+            Some(next) => target == next,
+            None => !self.blocks.iter().any(|b| &b.id == target),
+        };
+        if !falls_through {
+            jumps.push((Instruction::Jump(String::new()), target.clone()));
+        }
+        
+        jumps
+    }
+    
     pub(crate) fn relabel_blocks(&mut self) {
         let mut remapping = std::collections::HashMap::new();
         
-        for (i, block) in self.blocks.iter().enumerate() {
-            remapping.insert(block.id.clone(), BasicBlockId(i));
+        // (the entry block always keeps id 0, even if it isn't the first block)
+        remapping.insert(BasicBlockId(0), BasicBlockId(0));
+        for block in self.blocks.iter().filter(|block| block.id.0 != 0) {
+            remapping.insert(block.id.clone(), BasicBlockId(remapping.len()));
         }
         
         let end_block = BasicBlockId(self.blocks.len());
@@ -172,6 +275,76 @@ impl ProgramControlFlowGraph {
                 *id = remapping.get(id).cloned().unwrap_or(end_block.clone());
             }
         }
+        self.invalidate_structure();
+    }
+    
+    /// a human-readable listing of every block, with its instructions and jumps, followed by the loops.
+    pub fn dump(&self) -> String {
+        use std::fmt::Write;
+        
+        let structure = self.structure();
+        let ids: std::collections::HashSet<_> = self.blocks.iter().map(|block| &block.id).collect();
+        let name = |id: &BasicBlockId| match ids.contains(id) {
+            true => format!("Block {}", id.0),
+            false => "End".to_string(),
+        };
+        let names = |ids: &mut dyn Iterator<Item = String>| ids.collect::<Vec<_>>().join(", ");
+        
+        let mut out = String::new();
+        
+        for block in self.blocks.iter() {
+            writeln!(out, "Block {:?}:", block.id.0).unwrap();
+            
+            match &block.incoming_jumps[..] {
+                [] => if block.id.0 != 0 { writeln!(out, "  (DEAD BLOCK)").unwrap() },
+                jumps => {
+                    writeln!(out, "  Incoming jumps:").unwrap();
+                    for (id, flag) in jumps {
+                        writeln!(out, "    -> Block {:?} ({:?})", id.0, flag).unwrap();
+                    }
+                    writeln!(out).unwrap();
+                },
+            }
+            
+            if structure.dominators.contains(&block.id) {
+                let dominator = structure.dominators.immediate_dominator(&block.id).map_or("-".to_string(), name);
+                let post_dominator = match structure.post_dominators.contains(&block.id) {
+                    true => structure.post_dominators.immediate_dominator(&block.id).map_or("End".to_string(), name),
+                    false => "- (never ends)".to_string(),
+                };
+                writeln!(out, "  Immediate dominator: {dominator}").unwrap();
+                writeln!(out, "  Immediate post-dominator: {post_dominator}").unwrap();
+                writeln!(out, "  Dominance frontier: [{}]", names(&mut structure.frontiers[&block.id].iter().map(name))).unwrap();
+                writeln!(out, "  Loop depth: {}", structure.loop_depth(&block.id)).unwrap();
+                writeln!(out).unwrap();
+            }
+            
+            for (i, inst) in block.instructions.iter().enumerate() {
+                match block.source_lines.get(i).filter(|lines| !lines.is_empty()) {
+                    Some(lines) => writeln!(out, "  {:<24} ({})", format!("{inst:?}"), format_lines(lines)).unwrap(),
+                    None => writeln!(out, "  {inst:?}").unwrap(),
+                }
+            }
+            
+            writeln!(out, "  Outgoing jumps:").unwrap();
+            for (id, flag) in block.outgoing_jumps.iter() {
+                writeln!(out, "    -> Block {:?} ({:?})", id.0, flag).unwrap();
+            }
+            writeln!(out).unwrap();
+        }
+        
+        for (i, l) in structure.loops.iter().enumerate() {
+            writeln!(out, "Loop {i} (header: {}, depth: {}):", name(&l.header), l.depth).unwrap();
+            if let Some(parent) = l.parent {
+                writeln!(out, "  Inside of: Loop {parent}").unwrap();
+            }
+            writeln!(out, "  Blocks: [{}]", names(&mut l.blocks.iter().map(name))).unwrap();
+            writeln!(out, "  Latches: [{}]", names(&mut l.latches.iter().map(name))).unwrap();
+            writeln!(out, "  Exits: [{}]", names(&mut l.exits.iter().map(|(from, to)| format!("{} -> {}", name(from), name(to))))).unwrap();
+            writeln!(out).unwrap();
+        }
+        
+        out
     }
 }
 
@@ -181,45 +354,90 @@ impl From<&Program> for ProgramControlFlowGraph {
     }
 }
 
-impl Into<Program> for &ProgramControlFlowGraph {
-    fn into(self) -> Program {
-        let mut label_num: usize = 1;
+impl From<&ProgramControlFlowGraph> for Program {
+    fn from(graph: &ProgramControlFlowGraph) -> Self {
+        // lower the outgoing jumps of every block into actual jump instructions
+        let lowered_jumps: Vec<Vec<(Instruction, BasicBlockId)>> = graph.blocks.iter().enumerate()
+            .map(|(i, block)| {
+                let next_block = graph.blocks.get(i + 1).map(|b| b.id.clone());
+                graph.lower_outgoing_jumps(block, next_block.as_ref())
+            })
+            .collect();
+        
+        // if the entry block isn't first, the program needs to start by jumping to it
+        let entry_jump = graph.blocks.first()
+            .is_some_and(|block| block.id.0 != 0)
+            .then(|| (Instruction::Jump(String::new()), BasicBlockId(0)));
+        
+        // give every block that actually gets jumped to a label, in program order
+        let mut labels = std::collections::HashMap::<BasicBlockId, String>::new();
+        let jump_targets: std::collections::HashSet<_> = lowered_jumps.iter().flatten()
+            .chain(entry_jump.iter())
+            .map(|(_, id)| id)
+            .collect();
+        for block in graph.blocks.iter() {
+            if jump_targets.contains(&block.id) {
+                labels.insert(block.id.clone(), Program::label_name(labels.len()));
+            }
+        }
+        
+        // any remaining targets don't exist in the graph, so they point to the end of the program
+        let end_label = jump_targets.iter()
+            .any(|id| !labels.contains_key(id))
+            .then(|| Program::label_name(labels.len()));
+        
         let mut instructions = Vec::new();
+        let mut source_lines = Vec::new();
+        let mut comments = Vec::new();
         let mut label_map = std::collections::HashMap::<String, usize>::new();
         
-        for block in self.blocks.iter() {
-            for _ in block.incoming_jumps.iter() {
-                label_map.insert(format!("L{}", label_num), instructions.len());
-                label_num += 1;
+        if let Some((_, entry)) = &entry_jump {
+            instructions.push(Instruction::Jump(labels[entry].clone()));
+            // (this jump doesn't come from anywhere in the original program)
+            source_lines.push(SourceLines::new());
+        }
+        
+        for (block, jumps) in graph.blocks.iter().zip(lowered_jumps) {
+            if let Some(label) = labels.get(&block.id) {
+                label_map.insert(label.clone(), instructions.len());
             }
             
-            for inst in block.instructions.iter() {
-                instructions.push(inst.clone());
-            }
+            // (the comments at the end of a block come before the label of the next one, and so do the
+            // ones at the very start of the program, since there's no block before them)
+            let mut block_comments = block.comments.clone();
+            block_comments.sort_by_key(|(i, _)| *i);
+            comments.extend(block_comments.into_iter().map(|(i, index)| Comment {
+                line: instructions.len() + i,
+                index,
+                before_label: (i == block.instructions.len() && i > 0) || instructions.len() + i == 0,
+            }));
             
-            let previous_flag = JumpFlag::Never;
-            for (_id, flag) in block.outgoing_jumps.iter() {
-                instructions.push(match flag {
-                    JumpFlag::Always => Instruction::Jump("".to_string()),
-                    JumpFlag::IfNegative => Instruction::JumpN("".to_string()),
-                    JumpFlag::IfZero => Instruction::JumpZ("".to_string()),
-                    uh_oh => {
-                        println!("bad jump: {:?}", uh_oh);
-                        todo!()
-                    }
+            instructions.extend(block.instructions.iter().cloned());
+            source_lines.extend((0..block.instructions.len()).map(|i| block.source_lines.get(i).cloned().unwrap_or_default()));
+            
+            for (jump, target) in jumps {
+                let label = labels.get(&target).or(end_label.as_ref()).unwrap().clone();
+                instructions.push(match jump {
+                    Instruction::Jump(_) => Instruction::Jump(label),
+                    Instruction::JumpZ(_) => Instruction::JumpZ(label),
+                    Instruction::JumpN(_) => Instruction::JumpN(label),
+                    _ => unreachable!("lowered jumps should only contain jump instructions"),
                 });
+                source_lines.push(block.jump_lines.clone());
             }
         }
         
-        println!("{:?}", label_map);
-        for (i, instr) in instructions.iter().enumerate() {
-            println!("{i:3}. {instr:?}")
+        if let Some(label) = end_label {
+            label_map.insert(label, instructions.len());
         }
         
         Program {
             instructions,
-            initial_floor: self.initial_floor.clone(),
-            jump_label_lines: label_map
+            initial_floor: graph.initial_floor.clone(),
+            jump_label_lines: label_map,
+            comments,
+            drawings: graph.drawings.clone(),
+            source_lines,
         }
     }
 }
@@ -0,0 +1,338 @@
+//! a generic dataflow analysis framework (Kildall's method).
+//!
+//! an analysis just has to say what its facts look like (a [`Lattice`]) and how a single
+//! instruction changes them, and [`solve`] takes care of pushing the facts around the
+//! control flow graph until nothing changes anymore.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::instruction::Instruction;
+
+use super::{
+    basic_blocks::{BasicBlock, BasicBlockId},
+    control_flow_graph::ProgramControlFlowGraph,
+    jump_flag::JumpFlag,
+};
+
+pub trait Lattice: Clone + PartialEq {
+    /// merges the facts from another path through the program into this one
+    /// (i.e. replaces `self` with the least upper bound of `self` and `other`).
+    fn join(&mut self, other: &Self);
+    
+    /// jumps ahead from `previous` to (an upper bound of) `self`, so that lattices
+    /// with infinitely long chains (like intervals) still reach a fix point.
+    ///
+    /// this only gets used once a block has been visited more than `widen_after` times.
+    /// by default, it doesn't do anything, which is fine for lattices with a finite height.
+    fn widen(&mut self, previous: &Self) {
+        let _ = previous;
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// facts flow from the start of the program to the end (e.g. constant propagation)
+    Forward,
+    
+    /// facts flow from the end of the program to the start (e.g. liveness)
+    Backward,
+}
+
+pub trait DataflowAnalysis {
+    type Fact: Lattice;
+    
+    const DIRECTION: Direction;
+    
+    /// the facts at the start of the program (for a forward analysis)
+    /// or at the end of the program (for a backward analysis).
+    fn boundary(&self, graph: &ProgramControlFlowGraph) -> Self::Fact;
+    
+    /// the bottom of the lattice, i.e. what every block starts out with
+    /// (which should usually mean "this code is never reached").
+    fn bottom(&self, graph: &ProgramControlFlowGraph) -> Self::Fact;
+    
+    /// updates the facts to what they are after `instruction` runs (for a forward
+    /// analysis), or before it runs (for a backward analysis).
+    fn transfer(&self, fact: &mut Self::Fact, instruction: &Instruction);
+    
+    /// refines the facts along a jump that only gets taken when the accumulator
+    /// matches `flag` (e.g. hands are zero along the taken edge of a `JUMPZ`).
+    fn transfer_edge(&self, fact: &mut Self::Fact, flag: JumpFlag) {
+        let _ = (fact, flag);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DataflowOptions {
+    /// the maximum number of times blocks get visited in total, after which the analysis
+    /// gives up (see [`DataflowResult::converged`]). `None` means there is no limit.
+    pub iteration_limit: Option<usize>,
+    
+    /// how many times a single block can be visited before [`Lattice::widen`] kicks in.
+    pub widen_after: usize,
+}
+
+impl Default for DataflowOptions {
+    fn default() -> Self {
+        Self { iteration_limit: Some(100_000), widen_after: 8 }
+    }
+}
+
+/// the facts at the start and end of every block, as computed by [`solve`].
+#[derive(Debug, Clone)]
+pub struct DataflowResult<F> {
+    /// facts right before the first instruction of each block
+    start: HashMap<BasicBlockId, F>,
+    
+    /// facts right after the last instruction of each block (before any jumps)
+    end: HashMap<BasicBlockId, F>,
+    
+    /// the total number of times a block was visited
+    pub iterations: usize,
+    
+    /// false if the analysis hit the iteration limit, in which case the facts aren't
+    /// safe to use for anything.
+    pub converged: bool,
+}
+
+impl<F: Lattice> DataflowResult<F> {
+    /// the facts right before the first instruction of a block.
+    pub fn block_start(&self, block: &BasicBlockId) -> &F {
+        &self.start[block]
+    }
+    
+    /// the facts right after the last instruction of a block.
+    pub fn block_end(&self, block: &BasicBlockId) -> &F {
+        &self.end[block]
+    }
+    
+    /// the facts in between every instruction of a block, where `result[i]` is right
+    /// before instruction `i`, and the last one is at the end of the block.
+    pub fn within_block<A: DataflowAnalysis<Fact = F>>(&self, analysis: &A, block: &BasicBlock) -> Vec<F> {
+        match A::DIRECTION {
+            Direction::Forward => {
+                let mut fact = self.block_start(&block.id).clone();
+                let mut result = Vec::with_capacity(block.instructions.len() + 1);
+                for instruction in block.instructions.iter() {
+                    result.push(fact.clone());
+                    analysis.transfer(&mut fact, instruction);
+                }
+                result.push(fact);
+                result
+            },
+            Direction::Backward => {
+                let mut fact = self.block_end(&block.id).clone();
+                let mut result = Vec::with_capacity(block.instructions.len() + 1);
+                for instruction in block.instructions.iter().rev() {
+                    result.push(fact.clone());
+                    analysis.transfer(&mut fact, instruction);
+                }
+                result.push(fact);
+                result.reverse();
+                result
+            },
+        }
+    }
+    
+    /// the facts along each jump out of a block (for a forward analysis), with the
+    /// flags narrowed down to when each jump is actually taken.
+    pub fn outgoing<A: DataflowAnalysis<Fact = F>>(&self, analysis: &A, block: &BasicBlock) -> Vec<(BasicBlockId, JumpFlag, F)> {
+        block.effective_outgoing_jumps().into_iter()
+            .map(|(target, flag)| {
+                let mut fact = self.block_end(&block.id).clone();
+                analysis.transfer_edge(&mut fact, flag);
+                (target, flag, fact)
+            })
+            .collect()
+    }
+}
+
+/// runs a dataflow analysis over the whole graph until it reaches a fix point.
+pub fn solve<A: DataflowAnalysis>(analysis: &A, graph: &ProgramControlFlowGraph, options: DataflowOptions) -> DataflowResult<A::Fact> {
+    let index: HashMap<_, _> = graph.blocks.iter().enumerate()
+        .map(|(i, block)| (block.id.clone(), i))
+        .collect();
+    
+    // every edge in the graph, as `(from, to, flag)`, where `to` is `None` for jumps to the end of the program
+    let mut predecessors = vec![Vec::new(); graph.blocks.len()];
+    let mut successors = vec![Vec::new(); graph.blocks.len()];
+    for (i, block) in graph.blocks.iter().enumerate() {
+        for (target, flag) in block.effective_outgoing_jumps() {
+            let j = index.get(&target).copied();
+            successors[i].push((j, flag));
+            if let Some(j) = j {
+                predecessors[j].push((i, flag));
+            }
+        }
+    }
+    
+    let boundary = analysis.boundary(graph);
+    let bottom = analysis.bottom(graph);
+    
+    // `input` is where the facts flow into each block, and `output` is where they flow out
+    let mut input = vec![bottom.clone(); graph.blocks.len()];
+    let mut output = vec![bottom.clone(); graph.blocks.len()];
+    let mut visits = vec![0; graph.blocks.len()];
+    
+    // NOTE: visiting the blocks in program order (or reverse program order for a backward
+    //       analysis) usually gets to the fix point a lot faster.
+    let mut worklist: VecDeque<usize> = match A::DIRECTION {
+        Direction::Forward => (0..graph.blocks.len()).collect(),
+        Direction::Backward => (0..graph.blocks.len()).rev().collect(),
+    };
+    let mut queued = vec![true; graph.blocks.len()];
+    
+    let mut iterations = 0;
+    let mut converged = true;
+    
+    while let Some(i) = worklist.pop_front() {
+        queued[i] = false;
+        
+        if options.iteration_limit.is_some_and(|limit| iterations >= limit) {
+            converged = false;
+            break;
+        }
+        iterations += 1;
+        visits[i] += 1;
+        
+        // merge everything flowing into this block
+        let mut fact = bottom.clone();
+        match A::DIRECTION {
+            Direction::Forward => {
+                if graph.blocks[i].id.0 == 0 {
+                    fact.join(&boundary);
+                }
+                for &(from, flag) in predecessors[i].iter() {
+                    let mut edge = output[from].clone();
+                    analysis.transfer_edge(&mut edge, flag);
+                    fact.join(&edge);
+                }
+            },
+            Direction::Backward => {
+                for &(to, flag) in successors[i].iter() {
+                    let mut edge = match to {
+                        Some(to) => output[to].clone(),
+                        None => boundary.clone(),
+                    };
+                    analysis.transfer_edge(&mut edge, flag);
+                    fact.join(&edge);
+                }
+            },
+        }
+        
+        if visits[i] > options.widen_after {
+            fact.widen(&input[i]);
+        }
+        input[i] = fact.clone();
+        
+        // push it through the block
+        let block = &graph.blocks[i];
+        match A::DIRECTION {
+            Direction::Forward => block.instructions.iter().for_each(|inst| analysis.transfer(&mut fact, inst)),
+            Direction::Backward => block.instructions.iter().rev().for_each(|inst| analysis.transfer(&mut fact, inst)),
+        }
+        
+        if fact != output[i] {
+            output[i] = fact;
+            
+            let next: Vec<usize> = match A::DIRECTION {
+                Direction::Forward => successors[i].iter().filter_map(|&(to, _)| to).collect(),
+                Direction::Backward => predecessors[i].iter().map(|&(from, _)| from).collect(),
+            };
+            for j in next {
+                if !queued[j] {
+                    queued[j] = true;
+                    worklist.push_back(j);
+                }
+            }
+        }
+    }
+    
+    let (start, end) = match A::DIRECTION {
+        Direction::Forward => (input, output),
+        Direction::Backward => (output, input),
+    };
+    
+    let ids = graph.blocks.iter().map(|block| block.id.clone());
+    DataflowResult {
+        start: ids.clone().zip(start).collect(),
+        end: ids.zip(end).collect(),
+        iterations,
+        converged,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::program::Program;
+    
+    use super::*;
+    
+    /// how many instructions run before each point in the program, which keeps growing around loops.
+    #[derive(Debug, Clone, PartialEq)]
+    struct Steps(u32);
+    
+    impl Lattice for Steps {
+        fn join(&mut self, other: &Self) {
+            self.0 = self.0.max(other.0);
+        }
+        
+        fn widen(&mut self, previous: &Self) {
+            if self.0 > previous.0 { self.0 = u32::MAX }
+        }
+    }
+    
+    struct CountSteps;
+    
+    impl DataflowAnalysis for CountSteps {
+        type Fact = Steps;
+        const DIRECTION: Direction = Direction::Forward;
+        
+        fn boundary(&self, _: &ProgramControlFlowGraph) -> Steps { Steps(0) }
+        fn bottom(&self, _: &ProgramControlFlowGraph) -> Steps { Steps(0) }
+        fn transfer(&self, steps: &mut Steps, _: &Instruction) { steps.0 = steps.0.saturating_add(1) }
+    }
+    
+    /// a loop that counts down from whatever is in the inbox, which jumps back to `loop_target`.
+    fn countdown(loop_target: &str) -> ProgramControlFlowGraph {
+        let program = Program::from_asm(&format!("\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+    INBOX
+    COPYTO   0
+a:
+    COPYFROM 0
+    OUTBOX
+    BUMPDN   0
+    JUMPZ    b
+    JUMPN    b
+    JUMP     {loop_target}
+b:
+    COPYFROM [5]
+    OUTBOX
+")).unwrap();
+        ProgramControlFlowGraph::new(&program)
+    }
+    
+    #[test]
+    fn dataflow_widens_loops_and_gives_up_at_the_limit() {
+        let graph = countdown("a");
+        let start = |result: &DataflowResult<Steps>, block| result.block_start(&BasicBlockId(block)).0;
+        
+        // without widening, the loop would go on until the counter saturates
+        let result = solve(&CountSteps, &graph, DataflowOptions::default());
+        assert!(result.converged);
+        assert!(result.iterations < 30, "{}", result.iterations);
+        assert_eq!((start(&result, 0), start(&result, 1), start(&result, 2)), (0, u32::MAX, u32::MAX));
+        
+        let options = DataflowOptions { iteration_limit: Some(50), widen_after: usize::MAX };
+        let result = solve(&CountSteps, &graph, options);
+        assert!(!result.converged);
+        assert_eq!(result.iterations, 50);
+        
+        // (a graph without loops gets exact answers)
+        let result = solve(&CountSteps, &countdown("b"), DataflowOptions::default());
+        assert!(result.converged);
+        assert_eq!((start(&result, 0), start(&result, 1), start(&result, 2)), (0, 2, 5));
+    }
+}
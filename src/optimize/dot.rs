@@ -0,0 +1,79 @@
+//! exporting a control flow graph to graphviz's DOT format.
+//!
+//! the entry block is drawn in bold, blocks that can never be reached are greyed out, and
+//! jumps back to the start of a loop (see [`structure`](super::structure)) are drawn dashed.
+//! (render with e.g. `dot -Tsvg`)
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use super::{
+    basic_blocks::BasicBlockId,
+    control_flow_graph::ProgramControlFlowGraph,
+    remarks::describe,
+};
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl ProgramControlFlowGraph {
+    /// the graph in graphviz's DOT format, as a digraph called `name`.
+    ///
+    /// each block lists its instructions, and each jump is labelled with the values in hands it's taken for.
+    pub fn to_dot(&self, name: &str) -> String {
+        let structure = self.structure();
+        let reachable = |id: &BasicBlockId| structure.dominators.contains(id);
+        let ids: HashSet<_> = self.blocks.iter().map(|block| block.id.clone()).collect();
+        
+        let mut out = String::new();
+        writeln!(out, "digraph \"{}\" {{", escape(name)).unwrap();
+        writeln!(out, "    node [shape=box, fontname=monospace];").unwrap();
+        writeln!(out, "    edge [fontname=monospace];").unwrap();
+        
+        let mut jumps_to_end = false;
+        
+        for block in self.blocks.iter() {
+            let mut label = format!("block {}", block.id.0);
+            if block.id.0 == 0 {
+                label.push_str(" (entry)");
+            }
+            if !reachable(&block.id) {
+                label.push_str(" (dead)");
+            }
+            label.push_str("\\l");
+            for instruction in block.instructions.iter() {
+                label.push_str(&escape(&describe(instruction)));
+                label.push_str("\\l");
+            }
+            
+            let style = match (block.id.0 == 0, reachable(&block.id)) {
+                (true, _) => ", style=bold, penwidth=2",
+                (false, false) => ", style=\"filled,dashed\", fillcolor=lightgrey, fontcolor=grey40",
+                (false, true) => "",
+            };
+            writeln!(out, "    block{} [label=\"{label}\"{style}];", block.id.0).unwrap();
+        }
+        
+        for block in self.blocks.iter() {
+            for (target, flag) in block.effective_outgoing_jumps() {
+                let target_node = if ids.contains(&target) {
+                    format!("block{}", target.0)
+                } else {
+                    jumps_to_end = true;
+                    "end".to_string()
+                };
+                
+                let style = if structure.back_edges.contains(&(block.id.clone(), target.clone())) { ", style=dashed, color=blue" } else { "" };
+                writeln!(out, "    block{} -> {target_node} [label=\"{flag}\"{style}];", block.id.0).unwrap();
+            }
+        }
+        
+        if jumps_to_end {
+            writeln!(out, "    end [shape=doublecircle, label=\"end\"];").unwrap();
+        }
+        
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
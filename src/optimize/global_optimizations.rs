@@ -0,0 +1,246 @@
+use crate::{datacube::DataCube, instruction::{Instruction, Address}};
+
+use super::{
+    constants::{sign, KnownValues, State, Value},
+    control_flow_graph::ProgramControlFlowGraph,
+    jump_flag::JumpFlag,
+    liveness::Liveness,
+    ranges::Ranges,
+    remarks::describe,
+};
+
+/// removes `COPYTO`s to tiles that never get read before being overwritten
+/// (or before the program ends).
+/// 
+/// NOTE: `COPYTO [n]` is never removed, since it isn't known which tile it writes to.
+pub fn remove_dead_stores(graph: &mut ProgramControlFlowGraph) -> bool {
+    let liveness = Liveness::compute(graph);
+    let mut modified = false;
+    
+    for block in graph.blocks.iter_mut() {
+        let live_after = liveness.live_after(block);
+        
+        let dead_stores: Vec<usize> = block.instructions.iter().enumerate()
+            .filter(|(i, instruction)| matches!(instruction, Instruction::CopyTo(Address::Direct(a)) if !live_after[*i].contains(*a)))
+            .map(|(i, _)| i)
+            .collect();
+        
+        for &i in dead_stores.iter().rev() {
+            graph.remarks.transformation(block.lines(i..i + 1), format!(
+                "removed {}, since that tile never gets read before it's overwritten (or the program ends)",
+                describe(&block.instructions[i]),
+            ));
+            block.remove_instruction(i);
+        }
+        
+        modified |= !dead_stores.is_empty();
+    }
+    
+    modified
+}
+
+/// uses what's known about the values in hands and on the floor to:
+/// - remove `COPYFROM`s and `COPYTO`s that don't change anything, and arithmetic that leaves hands the same
+/// - turn indirect addresses into direct ones, when it's known which tile they point to
+/// - turn `ADD`s and `SUB`s with a known result into a `COPYFROM` of a tile that already holds it
+/// - resolve the jumps at the end of a block when it's known what's in hands
+pub fn propagate_constants(graph: &mut ProgramControlFlowGraph) -> bool {
+    use Instruction::*;
+    
+    let known = KnownValues::compute(graph);
+    let mut modified = false;
+    
+    for block in graph.blocks.iter_mut() {
+        let states = known.within_block(block);
+        let mut to_remove = Vec::new();
+        
+        for (i, state) in states.iter().enumerate().take(block.instructions.len()) {
+            let Some(state) = state else { continue };
+            let Some(after) = &states[i + 1] else { continue };
+            let instruction = &block.instructions[i];
+            let description = describe(instruction);
+            let lines = block.lines(i..i + 1);
+            
+            // why the instruction doesn't change anything, if it doesn't
+            let unchanged = match instruction {
+                CopyFrom(a) | CopyTo(a) if state.holds_hands(a) => Some("hands and the tile already hold the same value"),
+                Add(a) | Sub(a) if *state.tile(a) == Value::Known(DataCube::Number(0)) => Some("the tile is always 0"),
+                Add(_) | Sub(_) if state.hands != Value::Unknown && after.hands == state.hands => Some("it never changes what's in hands"),
+                _ => None,
+            };
+            if let Some(reason) = unchanged {
+                graph.remarks.transformation(lines, format!("removed {description}, since {reason}"));
+                to_remove.push(i);
+                continue;
+            }
+            
+            // the tile that an indirect address points to
+            let direct = match instruction {
+                CopyFrom(a @ Address::Indirect(_)) | CopyTo(a @ Address::Indirect(_)) | Add(a @ Address::Indirect(_))
+                | Sub(a @ Address::Indirect(_)) | BumpUp(a @ Address::Indirect(_)) | BumpDn(a @ Address::Indirect(_))
+                    => state.resolve(a).map(Address::Direct),
+                _ => None,
+            };
+            
+            // a tile that already holds the result of some arithmetic
+            let folded = match (instruction, &after.hands) {
+                (Add(_) | Sub(_), Value::Known(result)) => state.floor.iter()
+                    .position(|tile| *tile == Value::Known(result.clone()))
+                    .map(|tile| (tile, result.clone())),
+                _ => None,
+            };
+            
+            let replacement = match (instruction, direct, folded) {
+                (_, _, Some((tile, result))) => {
+                    let replacement = CopyFrom(Address::Direct(tile));
+                    graph.remarks.transformation(lines, format!("replaced {description} with {}, since the result is always {result}", describe(&replacement)));
+                    replacement
+                },
+                (_, Some(address), None) => {
+                    let replacement = match instruction {
+                        CopyFrom(_) => CopyFrom(address),
+                        CopyTo(_) => CopyTo(address),
+                        Add(_) => Add(address),
+                        Sub(_) => Sub(address),
+                        BumpUp(_) => BumpUp(address),
+                        _ => BumpDn(address),
+                    };
+                    graph.remarks.transformation(lines, format!("replaced {description} with {}, since that's always the tile it points to", describe(&replacement)));
+                    replacement
+                },
+                _ => continue,
+            };
+            block.instructions[i] = replacement;
+            modified = true;
+        }
+        
+        for &i in to_remove.iter().rev() {
+            block.remove_instruction(i);
+        }
+        modified |= !to_remove.is_empty();
+        
+        // a jump that's taken for whatever is known to be in hands is the only one that can be taken
+        if let Some(State { hands: Value::Known(cube), .. }) = known.block_end(&block.id) {
+            let jumps = block.effective_outgoing_jumps();
+            if jumps.len() > 1 {
+                let (target, _) = jumps.into_iter().find(|(_, flag)| flag.contains(sign(cube))).unwrap();
+                graph.remarks.transformation(block.lines(0..block.instructions.len()), format!(
+                    "made block {} always jump to block {}, since hands are always {cube} at the end of it",
+                    block.id.0, target.0,
+                ));
+                block.outgoing_jumps = vec![(target, JumpFlag::Always)];
+                modified = true;
+            }
+        }
+    }
+    
+    modified
+}
+
+/// uses the range of values that could be in hands at the end of each block to narrow down the
+/// conditions its jumps are taken for, and removes the jumps that can never be taken.
+/// 
+/// this also warns about instructions that always overflow.
+pub fn narrow_jumps(graph: &mut ProgramControlFlowGraph) -> bool {
+    let Some(ranges) = Ranges::compute(graph) else { return false };
+    let mut modified = false;
+    
+    for block in graph.blocks.iter_mut() {
+        for (i, state) in ranges.within_block(block).iter().enumerate().take(block.instructions.len()) {
+            let Some(arithmetic) = state.as_ref().and_then(|state| state.arithmetic(&block.instructions[i])) else { continue };
+            if arithmetic.always_overflows() {
+                graph.remarks.warning(block.lines(i..i + 1), format!(
+                    "{} will always overflow, since the result is never between -999 and 999",
+                    describe(&block.instructions[i]),
+                ));
+            }
+        }
+        
+        // (hands being empty at a conditional jump is an error, so there's nothing to narrow down)
+        let Some(state) = ranges.block_end(&block.id) else { continue };
+        let signs = state.hands.signs();
+        if signs == JumpFlag::Never {
+            continue;
+        }
+        
+        let jumps = block.effective_outgoing_jumps();
+        let mut narrowed: Vec<_> = jumps.iter()
+            .map(|(target, flag)| (target.clone(), *flag & signs))
+            .filter(|(_, flag)| *flag != JumpFlag::Never)
+            .collect();
+        
+        // the signs that hands never have can go to any of the jumps, so they go to the last one
+        // (which makes it unconditional if it's the only one left)
+        if let Some((_, flag)) = narrowed.last_mut() {
+            *flag |= !signs;
+        }
+        
+        if narrowed != jumps {
+            graph.remarks.transformation(block.lines(0..block.instructions.len()), format!(
+                "narrowed down the jumps at the end of block {}, since hands are always {signs} there",
+                block.id.0,
+            ));
+            block.outgoing_jumps = narrowed;
+            modified = true;
+        }
+    }
+    
+    modified
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::program::Program;
+    
+    use super::*;
+    
+    #[test]
+    fn dead_stores_in_loops() {
+        // tile 0 is read on every trip around the loop, so both stores to it stay. tile 2 gets
+        // overwritten at the start of the loop before it's read, so the store before the loop is
+        // dead, and tile 3 is never read at all
+        let mut program = Program::from_asm("\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+    INBOX
+    COPYTO   0
+    COPYTO   2
+    COPYTO   3
+a:
+    INBOX
+    COPYTO   2
+    ADD      0
+    COPYTO   0
+    SUB      2
+    JUMPZ    b
+    JUMP     a
+b:
+    COPYFROM 0
+    OUTBOX
+").unwrap();
+        program.initial_floor = vec![None; 16];
+        
+        let mut graph = ProgramControlFlowGraph::new(&program);
+        while graph.run_optimization_pass(remove_dead_stores) {}
+        
+        let optimized: Program = (&graph).into();
+        assert_eq!(optimized.to_asm(), "\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+    INBOX   
+    COPYTO   0
+a:
+    INBOX   
+    COPYTO   2
+    ADD      0
+    COPYTO   0
+    SUB      2
+    JUMPZ    b
+    JUMP     a
+b:
+    COPYFROM 0
+    OUTBOX  
+
+");
+    }
+}
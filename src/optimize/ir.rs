@@ -0,0 +1,311 @@
+//! a textual form of the control flow graph, for writing graphs by hand (e.g. to test a single pass).
+//!
+//! it looks like this:
+//! ```text
+//! floor 16: 14=0, 15=4    -- optional, and `floor 16` for an empty floor
+//!
+//! block 0:                -- the entry block always has id 0, but doesn't have to come first
+//!     INBOX
+//!     COMMENT  1
+//!     jump zero -> block 2
+//!     jump always -> block 1
+//!
+//! block 1:
+//!     OUTBOX
+//!     jump always -> end
+//!
+//! block 2:
+//!     jump negative/positive -> block 0
+//!     jump zero -> end
+//! ```
+//!
+//! the blocks are in the order they get emitted in, and their jumps are checked in order, with
+//! any combination of zero, negative and positive as the condition (see [`JumpFlag`]'s `Display`),
+//! so they can express things that the game's jumps can't. `--` starts a comment, like in the game.
+
+use std::fmt::Write;
+
+use crate::{
+    datacube::DataCube,
+    errors::{AsmDiagnostic, AsmParseError, AsmParseErrors, IrParseError},
+    instruction::Instruction,
+    program::SourceLines,
+};
+
+use super::{
+    basic_blocks::{BasicBlock, BasicBlockId},
+    control_flow_graph::ProgramControlFlowGraph,
+    jump_flag::JumpFlag,
+    remarks::Remarks,
+};
+
+/// a jump in a block that's been parsed, before it's known whether its target exists.
+struct ParsedJump {
+    flag: JumpFlag,
+    
+    /// the id of the block it jumps to, or `None` for the end of the program
+    target: Option<usize>,
+    line: usize,
+    source_line: String,
+    span: std::ops::Range<usize>,
+}
+
+/// parses a floor like `16: 14=0, 15=4`.
+fn parse_floor(floor: &str) -> Result<Vec<Option<DataCube>>, AsmParseError> {
+    let (size, tiles) = floor.split_once(':').unwrap_or((floor, ""));
+    let size: usize = size.trim().parse().map_err(AsmParseError::IntParseError)?;
+    let mut result = vec![None; size];
+    
+    for tile in tiles.split(',').map(str::trim).filter(|tile| !tile.is_empty()) {
+        let (address, value) = tile.split_once('=').ok_or(AsmParseError::ExpectedToken("a tile, like `<tile>=<value>`"))?;
+        let address: usize = address.trim().parse().map_err(AsmParseError::IntParseError)?;
+        let value = value.trim();
+        let cube = match value.parse::<i32>() {
+            Ok(n) => DataCube::from_number(n).ok(),
+            Err(_) => value.parse().ok().and_then(DataCube::from_char),
+        };
+        match (result.get_mut(address), cube) {
+            (Some(tile), Some(cube)) => *tile = Some(cube),
+            (None, _) => return Err(AsmParseError::UnexpectedToken(tile.to_string())),
+            (_, None) => return Err(AsmParseError::UnexpectedToken(value.to_string())),
+        }
+    }
+    
+    Ok(result)
+}
+
+impl ProgramControlFlowGraph {
+    /// the graph in its textual form (see the [module docs](self)), which
+    /// [`ProgramControlFlowGraph::from_ir`] can parse again.
+    pub fn to_ir(&self) -> String {
+        let mut ir = String::new();
+        
+        if !self.initial_floor.is_empty() {
+            let tiles: Vec<_> = self.initial_floor.iter().enumerate()
+                .filter_map(|(i, tile)| tile.as_ref().map(|cube| format!("{i}={cube}")))
+                .collect();
+            match tiles.is_empty() {
+                true => writeln!(ir, "floor {}", self.initial_floor.len()).unwrap(),
+                false => writeln!(ir, "floor {}: {}", self.initial_floor.len(), tiles.join(", ")).unwrap(),
+            }
+            ir.push('\n');
+        }
+        
+        let ids: std::collections::HashSet<_> = self.blocks.iter().map(|block| &block.id).collect();
+        
+        for (i, block) in self.blocks.iter().enumerate() {
+            if i > 0 {
+                ir.push('\n');
+            }
+            writeln!(ir, "block {}:", block.id.0).unwrap();
+            
+            for i in 0..=block.instructions.len() {
+                for (_, comment) in block.comments.iter().filter(|(index, _)| *index == i) {
+                    writeln!(ir, "    {:<8} {comment}", "COMMENT").unwrap();
+                }
+                if let Some(instruction) = block.instructions.get(i) {
+                    writeln!(ir, "    {}", instruction.to_string().trim_end()).unwrap();
+                }
+            }
+            
+            for (target, flag) in block.outgoing_jumps.iter() {
+                match ids.contains(target) {
+                    true => writeln!(ir, "    jump {flag} -> block {}", target.0).unwrap(),
+                    false => writeln!(ir, "    jump {flag} -> end").unwrap(),
+                }
+            }
+        }
+        
+        ir
+    }
+    
+    /// parses a graph from its textual form (see the [module docs](self)).
+    ///
+    /// like [`Program::from_asm`](crate::program::Program::from_asm), this returns every error in the
+    /// text instead of stopping at the first one. the source lines of the instructions are their lines in `ir`.
+    pub fn from_ir(ir: &str) -> Result<Self, AsmParseErrors<IrParseError>> {
+        let mut errors = Vec::new();
+        let mut initial_floor = Vec::new();
+        let mut blocks: Vec<BasicBlock> = Vec::new();
+        
+        // the jumps of each block (in the same order as `blocks`), and where each block was declared
+        let mut jumps: Vec<Vec<ParsedJump>> = Vec::new();
+        let mut headers: Vec<(usize, &str)> = Vec::new();
+        
+        for (line_number, source_line) in ir.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+            let line = source_line.split("--").next().unwrap_or("");
+            
+            let spans: Vec<_> = line.split_whitespace()
+                .map(|tok| {
+                    let start = tok.as_ptr() as usize - line.as_ptr() as usize;
+                    start..start + tok.len()
+                })
+                .collect();
+            let tokens: Vec<_> = spans.iter().map(|span| &line[span.clone()]).collect();
+            let end_of_line = line.trim_end().len()..line.trim_end().len() + 1;
+            
+            let error_at = |error: AsmParseError, span: &std::ops::Range<usize>| {
+                AsmDiagnostic::new(IrParseError::Asm(error), line_number, source_line, span.clone())
+            };
+            
+            match tokens[..] {
+                [] => {},
+                ["floor"] => {
+                    errors.push(error_at(AsmParseError::ExpectedToken("the size of the floor"), &end_of_line));
+                },
+                ["floor", ..] => {
+                    let rest = spans[1].start..end_of_line.start;
+                    match parse_floor(&line[rest.clone()]) {
+                        Ok(floor) => initial_floor = floor,
+                        Err(error) => errors.push(error_at(error, &rest)),
+                    }
+                },
+                ["block", id] => {
+                    let Some(id) = id.strip_suffix(':') else {
+                        errors.push(error_at(AsmParseError::ExpectedToken("`:` after the block id"), &end_of_line));
+                        continue;
+                    };
+                    let id: usize = match id.parse() {
+                        Ok(id) => id,
+                        Err(error) => {
+                            errors.push(error_at(AsmParseError::IntParseError(error), &spans[1]));
+                            continue;
+                        },
+                    };
+                    if blocks.iter().any(|block| block.id.0 == id) {
+                        errors.push(AsmDiagnostic::new(IrParseError::DuplicateBlock(id), line_number, source_line, spans[1].clone()));
+                    }
+                    
+                    blocks.push(BasicBlock {
+                        id: BasicBlockId(id),
+                        instructions: Vec::new(),
+                        outgoing_jumps: Vec::new(),
+                        incoming_jumps: Vec::new(),
+                        comments: Vec::new(),
+                        source_lines: Vec::new(),
+                        jump_lines: SourceLines::new(),
+                    });
+                    jumps.push(Vec::new());
+                    headers.push((line_number, source_line));
+                },
+                _ if blocks.is_empty() => {
+                    errors.push(error_at(AsmParseError::ExpectedToken("a `block <id>:` line before this"), &spans[0]));
+                },
+                ["jump", flag, "->", ref target @ ..] => {
+                    let block = blocks.last_mut().unwrap();
+                    let Some(flag) = JumpFlag::from_name(flag) else {
+                        errors.push(error_at(AsmParseError::UnexpectedToken(flag.to_string()), &spans[1]));
+                        continue;
+                    };
+                    let target = match target {
+                        ["end"] => None,
+                        ["block", id] => match id.parse() {
+                            Ok(id) => Some(id),
+                            Err(error) => {
+                                errors.push(error_at(AsmParseError::IntParseError(error), &spans[4]));
+                                continue;
+                            },
+                        },
+                        [token, ..] if *token != "block" && *token != "end" => {
+                            errors.push(error_at(AsmParseError::UnexpectedToken(token.to_string()), &spans[3]));
+                            continue;
+                        },
+                        _ => {
+                            errors.push(error_at(AsmParseError::ExpectedToken("`end` or `block <id>`"), &(spans[3].start..end_of_line.end)));
+                            continue;
+                        },
+                    };
+                    
+                    block.jump_lines.insert(line_number);
+                    jumps.last_mut().unwrap().push(ParsedJump {
+                        flag,
+                        target,
+                        line: line_number,
+                        source_line: source_line.to_string(),
+                        span: spans[3].start..spans.last().unwrap().end,
+                    });
+                },
+                ["jump", ..] => {
+                    errors.push(error_at(AsmParseError::ExpectedToken("a jump, like `jump <condition> -> <target>`"), &(spans[0].start..end_of_line.end)));
+                },
+                ["COMMENT", index] => {
+                    let block = blocks.last_mut().unwrap();
+                    match index.parse() {
+                        Ok(index) => block.comments.push((block.instructions.len(), index)),
+                        Err(error) => errors.push(error_at(AsmParseError::IntParseError(error), &spans[1])),
+                    }
+                },
+                [_, _, extra, ..] => {
+                    errors.push(error_at(AsmParseError::UnexpectedToken(extra.to_string()), &spans[2]));
+                },
+                [name, ..] => {
+                    let block = blocks.last_mut().unwrap();
+                    match Instruction::parse_from_args(name, tokens.get(1).copied()) {
+                        // (the jumps between blocks are written as `jump`s instead)
+                        Ok(Instruction::Jump(_) | Instruction::JumpZ(_) | Instruction::JumpN(_)) => {
+                            errors.push(error_at(AsmParseError::UnexpectedToken(name.to_string()), &spans[0]));
+                        },
+                        Ok(instruction) => {
+                            block.instructions.push(instruction);
+                            block.source_lines.push(SourceLines::from([line_number]));
+                        },
+                        Err(error) => {
+                            let span = match (&error, spans.get(1)) {
+                                (AsmParseError::ExpectedToken(_), _) => &end_of_line,
+                                (AsmParseError::UnexpectedToken(token), _) if token == name => &spans[0],
+                                (_, Some(argument)) => argument,
+                                (_, None) => &spans[0],
+                            };
+                            errors.push(error_at(error, span));
+                        },
+                    }
+                },
+            }
+        }
+        
+        // jumps to the end of the program go to an id that isn't used by any block
+        let end = BasicBlockId(blocks.iter().map(|block| block.id.0 + 1).max().unwrap_or(0));
+        let ids: std::collections::HashSet<_> = blocks.iter().map(|block| block.id.0).collect();
+        
+        for ((block, jumps), (line_number, source_line)) in blocks.iter_mut().zip(jumps).zip(headers) {
+            let mut covered = JumpFlag::Never;
+            for jump in jumps {
+                covered |= jump.flag;
+                let target = match jump.target {
+                    Some(id) if ids.contains(&id) => BasicBlockId(id),
+                    Some(id) => {
+                        errors.push(AsmDiagnostic::new(AsmParseError::UnknownLabel(format!("block {id}")).into(), jump.line, &jump.source_line, jump.span));
+                        continue;
+                    },
+                    None => end.clone(),
+                };
+                block.outgoing_jumps.push((target, jump.flag));
+            }
+            
+            if covered != JumpFlag::Always {
+                errors.push(AsmDiagnostic::new(IrParseError::MissingJump(block.id.0), line_number, source_line, 0..source_line.len()));
+            }
+        }
+        
+        if !blocks.iter().any(|block| block.id.0 == 0) {
+            let last_line = ir.lines().count().max(1);
+            errors.push(AsmDiagnostic::new(AsmParseError::ExpectedToken("an entry block (`block 0:`)").into(), last_line, ir.lines().last().unwrap_or(""), 0..0));
+        }
+        
+        if !errors.is_empty() {
+            errors.sort_by_key(|error| (error.line, error.column));
+            return Err(AsmParseErrors(errors));
+        }
+        
+        let mut graph = Self {
+            initial_floor,
+            blocks,
+            drawings: Vec::new(),
+            remarks: Remarks::default(),
+            structure: Default::default(),
+        };
+        graph.refresh_incoming_jumps();
+        
+        Ok(graph)
+    }
+}
@@ -14,6 +14,22 @@ pub enum JumpFlag {
 
 #[allow(dead_code)]
 impl JumpFlag {
+    /// returns true if this flag jumps in every case that `other` does.
+    pub fn contains(self, other: Self) -> bool {
+        self & other == other
+    }
+    
+    /// every flag, in the same order as their bits.
+    pub const ALL: [Self; 8] = [
+        JumpFlag::Never, JumpFlag::IfZero, JumpFlag::IfNegative, JumpFlag::IfNotPositive,
+        JumpFlag::IfPositive, JumpFlag::IfNotNegative, JumpFlag::IfNotZero, JumpFlag::Always,
+    ];
+    
+    /// the flag with the given name (see the [`Display`](std::fmt::Display) impl).
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|flag| flag.to_string() == name)
+    }
+    
     fn from_u8(x: u8) -> Self {
         match x {
             0b000 => JumpFlag::Never,
@@ -75,3 +91,19 @@ impl std::ops::BitOrAssign for JumpFlag {
         *self = *self | rhs;
     }
 }
+
+impl std::fmt::Display for JumpFlag {
+    /// the values in hands that the jump is taken for (e.g. `zero/negative`).
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            JumpFlag::Never => "never",
+            JumpFlag::IfZero => "zero",
+            JumpFlag::IfNegative => "negative",
+            JumpFlag::IfNotPositive => "zero/negative",
+            JumpFlag::IfPositive => "positive",
+            JumpFlag::IfNotNegative => "zero/positive",
+            JumpFlag::IfNotZero => "negative/positive",
+            JumpFlag::Always => "always",
+        })
+    }
+}
@@ -0,0 +1,195 @@
+//! live variable analysis over the floor tiles.
+//!
+//! a tile is "live" at some point in the program if the value on it might get read
+//! before it gets overwritten. direct addresses are handled exactly, but an indirect
+//! access like `COPYFROM [n]` could read any tile at all, so it makes every tile live.
+
+use crate::instruction::{Instruction, Address};
+
+use super::{
+    basic_blocks::{BasicBlock, BasicBlockId},
+    control_flow_graph::ProgramControlFlowGraph,
+    dataflow::{self, DataflowAnalysis, DataflowOptions, DataflowResult, Direction, Lattice},
+};
+
+/// a set of floor tiles.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileSet(Vec<bool>);
+
+impl TileSet {
+    pub fn empty(size: usize) -> Self {
+        Self(vec![false; size])
+    }
+    
+    pub fn contains(&self, tile: usize) -> bool {
+        self.0.get(tile).copied().unwrap_or(false)
+    }
+    
+    pub fn insert(&mut self, tile: usize) {
+        self.0[tile] = true;
+    }
+    
+    pub fn remove(&mut self, tile: usize) {
+        self.0[tile] = false;
+    }
+    
+    pub fn insert_all(&mut self) {
+        self.0.fill(true);
+    }
+    
+    pub fn clear(&mut self) {
+        self.0.fill(false);
+    }
+    
+    /// removes every tile that isn't also in `other`.
+    pub fn intersect(&mut self, other: &Self) {
+        for (a, &b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a &= b;
+        }
+    }
+}
+
+/// updates the set of live tiles to what it is right *before* `instruction` runs,
+/// given what it is right after.
+pub fn transfer(live: &mut TileSet, instruction: &Instruction) {
+    use Instruction::*;
+    
+    match instruction {
+        CopyTo(Address::Direct(a)) => live.remove(*a),
+        // (an indirect write might not overwrite any particular tile, so it can't kill anything)
+        CopyTo(Address::Indirect(a)) => live.insert(*a),
+        
+        CopyFrom(Address::Direct(a)) | Add(Address::Direct(a)) | Sub(Address::Direct(a))
+        | BumpUp(Address::Direct(a)) | BumpDn(Address::Direct(a)) => live.insert(*a),
+        
+        CopyFrom(Address::Indirect(_)) | Add(Address::Indirect(_)) | Sub(Address::Indirect(_))
+        | BumpUp(Address::Indirect(_)) | BumpDn(Address::Indirect(_)) => live.insert_all(),
+        
+        Inbox | Outbox | Jump(_) | JumpZ(_) | JumpN(_) => {},
+    }
+}
+
+/// the analysis itself, for [`dataflow::solve`].
+struct LiveTiles {
+    floor_size: usize,
+}
+
+impl Lattice for TileSet {
+    fn join(&mut self, other: &Self) {
+        for (a, &b) in self.0.iter_mut().zip(other.0.iter()) {
+            *a |= b;
+        }
+    }
+}
+
+impl DataflowAnalysis for LiveTiles {
+    type Fact = TileSet;
+    
+    const DIRECTION: Direction = Direction::Backward;
+    
+    /// (nothing can read the floor after the program ends)
+    fn boundary(&self, _graph: &ProgramControlFlowGraph) -> TileSet {
+        TileSet::empty(self.floor_size)
+    }
+    
+    fn bottom(&self, _graph: &ProgramControlFlowGraph) -> TileSet {
+        TileSet::empty(self.floor_size)
+    }
+    
+    fn transfer(&self, live: &mut TileSet, instruction: &Instruction) {
+        transfer(live, instruction)
+    }
+}
+
+/// the tiles that are live at the start and end of every block in a control flow graph.
+#[derive(Debug, Clone)]
+pub struct Liveness {
+    result: DataflowResult<TileSet>,
+}
+
+impl Liveness {
+    pub fn compute(graph: &ProgramControlFlowGraph) -> Self {
+        let analysis = LiveTiles { floor_size: Self::floor_size(graph) };
+        
+        // (the lattice is finite, so there's no need for an iteration limit)
+        let options = DataflowOptions { iteration_limit: None, ..Default::default() };
+        
+        Self { result: dataflow::solve(&analysis, graph, options) }
+    }
+    
+    /// the number of tiles that need to be tracked: the whole floor, plus any
+    /// directly addressed tiles past the end of it (if the floor size is unknown).
+    pub(crate) fn floor_size(graph: &ProgramControlFlowGraph) -> usize {
+        use Instruction::*;
+        
+        graph.blocks.iter()
+            .flat_map(|block| block.instructions.iter())
+            .filter_map(|instruction| match instruction {
+                CopyFrom(a) | CopyTo(a) | Add(a) | Sub(a) | BumpUp(a) | BumpDn(a) => match a {
+                    Address::Direct(x) | Address::Indirect(x) => Some(x + 1),
+                },
+                _ => None,
+            })
+            .chain(std::iter::once(graph.initial_floor.len()))
+            .max()
+            .unwrap()
+    }
+    
+    /// the tiles that are live at the start of a block.
+    pub fn live_in(&self, block: &BasicBlockId) -> &TileSet {
+        self.result.block_start(block)
+    }
+    
+    /// the tiles that are live at the end of a block.
+    pub fn live_out(&self, block: &BasicBlockId) -> &TileSet {
+        self.result.block_end(block)
+    }
+    
+    /// the tiles that are live right after each instruction in a block.
+    pub fn live_after(&self, block: &BasicBlock) -> Vec<TileSet> {
+        let floor_size = self.result.block_end(&block.id).0.len();
+        let mut live = self.result.within_block(&LiveTiles { floor_size }, block);
+        live.remove(0);
+        live
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::program::Program;
+    
+    use super::*;
+    
+    #[test]
+    fn liveness_follows_loops_and_indirect_reads() {
+        // (a loop that counts down from whatever is in the inbox)
+        let mut program = Program::from_asm("\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+    INBOX
+    COPYTO   0
+a:
+    COPYFROM 0
+    OUTBOX
+    BUMPDN   0
+    JUMPZ    b
+    JUMPN    b
+    JUMP     a
+b:
+    COPYFROM [5]
+    OUTBOX
+").unwrap();
+        program.initial_floor = vec![None; 16];
+        
+        let graph = ProgramControlFlowGraph::new(&program);
+        let liveness = Liveness::compute(&graph);
+        let live = |tiles: &TileSet| (0..16).filter(|&tile| tiles.contains(tile)).collect::<Vec<_>>();
+        
+        assert_eq!(live(liveness.live_in(&BasicBlockId(0))), [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+        assert_eq!(live(liveness.live_in(&BasicBlockId(1))), (0..16).collect::<Vec<_>>());
+        
+        // (tile 0 is only read again if the loop goes around once more)
+        let live_after: Vec<_> = liveness.live_after(&graph.blocks[1]).iter().map(|tiles| tiles.contains(0)).collect();
+        assert_eq!(live_after, [true, true, true]);
+    }
+}
@@ -3,21 +3,22 @@ use crate::{optimize::basic_blocks::BasicBlock, instruction::Address};
 use super::basic_blocks::BasicBlockId;
 use super::jump_flag::JumpFlag;
 use super::control_flow_graph::{ProgramControlFlowGraph, Optimization};
+use super::remarks::{describe, Remarks};
 
 /// convert a function that optimizes a single block into an optimization pass
 /// for a full control flow graph.
-pub fn local_optimization<T: FnMut(&mut BasicBlock) -> bool>(mut block_optimization: T) -> impl Optimization {
+pub fn local_optimization<T: FnMut(&mut BasicBlock, &mut Remarks) -> bool>(mut block_optimization: T) -> impl Optimization {
     move |graph: &mut ProgramControlFlowGraph| {
         let mut modified = false;
         for block in graph.blocks.iter_mut() {
-            modified |= block_optimization(block);
+            modified |= block_optimization(block, &mut graph.remarks);
         }
         modified
     }
 }
 
 /// make all outgoing jumps in a given block as simple as possible.
-pub fn simplify_outgoing_jumps(block: &mut BasicBlock) -> bool {
+pub fn simplify_outgoing_jumps(block: &mut BasicBlock, remarks: &mut Remarks) -> bool {
     let mut result = false;
     
     // transforms `JUMPIF(cond1) a; JUMPIF(cond2) b;`
@@ -34,21 +35,22 @@ pub fn simplify_outgoing_jumps(block: &mut BasicBlock) -> bool {
     
     // combine all jumps to the same block
     // e.g. `JUMPIF(cond1) a; JUMPIF(cond2) a;` becomes `JUMPIF(cond1 || cond2) a;`
-    let mut uniq = std::collections::HashMap::new();
+    // (keeping the jumps in the order they first appear in, so that the output doesn't change from run to run)
+    let mut uniq: Vec<(usize, JumpFlag)> = Vec::new();
     for (BasicBlockId(target), cond) in block.outgoing_jumps.iter() {
-        match uniq.get_mut(target) {
-            Some(existing_cond) => {
+        match uniq.iter_mut().find(|(id, _)| id == target) {
+            Some((_, existing_cond)) => {
                 *existing_cond |= *cond;
                 result = true;
             },
             None => {
-                uniq.insert(*target, *cond);
+                uniq.push((*target, *cond));
             }
         }
     }
     
     // remove all jumps with a condition of `Never` and re-assign to jumps
-    block.outgoing_jumps = uniq.iter().filter_map(|(&id, &cond)| {
+    block.outgoing_jumps = uniq.into_iter().filter_map(|(id, cond)| {
         if cond == JumpFlag::Never {
             result = true;
             None
@@ -60,6 +62,10 @@ pub fn simplify_outgoing_jumps(block: &mut BasicBlock) -> bool {
     // the block needs to always jump *somewhere*, so this is just a sanity check
     debug_assert_eq!(block.outgoing_jumps.iter().map(|(_, c)| *c).reduce(|a, b| a | b).unwrap(), JumpFlag::Always);
     
+    if result {
+        remarks.transformation(block.lines(0..block.instructions.len()), format!("simplified the jumps at the end of block {}", block.id.0));
+    }
+    
     result
 }
 
@@ -69,25 +75,40 @@ pub fn simplify_outgoing_jumps(block: &mut BasicBlock) -> bool {
 /// dependency analysis, or anything like that. it's just a bunch of simple
 /// optimizations that are easy to implement and are only really likely to
 /// happen after multiple blocks are merged into one.
-pub fn peephole_optimizations(block: &mut BasicBlock) -> bool {
+pub fn peephole_optimizations(block: &mut BasicBlock, remarks: &mut Remarks) -> bool {
     use crate::instruction::Instruction::*;
     
+    // pairs of `(index, into)`, where `into` is the instruction that does the removed one's job
+    // (if there is one), for keeping track of where the instructions came from
     let mut to_remove = Vec::new();
     
+    // pairs of `(index, instruction)` to replace the instruction at `index` with
+    let mut to_replace = Vec::new();
+    
     // length two optimizations
     for (i, instrs) in block.instructions.windows(2).enumerate() {
+        // an instruction that's already going away can't be used to justify changing the next one
+        // (e.g. `ADD 1; SUB 1; ADD 1` would lose all three). the pass runs again anyway, so nothing gets missed
+        if to_remove.iter().any(|&(j, _)| j == i) || to_replace.iter().any(|(j, _)| *j == i) {
+            continue;
+        }
+        
+        let lines = block.lines(i..i + 2);
+        let (first, second) = (describe(&instrs[0]), describe(&instrs[1]));
+        
         match instrs {
             [ // statically detectable undefined behavior
                 Outbox,
                 Outbox | CopyTo(_) | Add(_) | Sub(_), // TODO: jumpz and jumpn?
             ] => {
-                eprintln!("Undefined behavior warning! An OUTBOX followed by a {instr:?} instruction will always raise a EmptyHands error.", instr = instrs[1]);
+                remarks.warning(lines, format!("{second} right after OUTBOX will always fail, since there's nothing in hands"));
             },
             [ // redundant accumulator instructions that immediately get overwritten
                 CopyFrom(_) | Add(_) | Sub(_),
                 CopyFrom(_) | BumpUp(_) | BumpDn(_),
             ] => {
-                to_remove.push(i);
+                remarks.transformation(lines, format!("removed {first}, since {second} overwrites hands right after it"));
+                to_remove.push((i, None));
             },
             [ // optimize redundant COPYFROM after writing to the same address
                 CopyTo(Address::Direct(a)) | BumpUp(Address::Direct(a)) | BumpDn(Address::Direct(a)),
@@ -102,30 +123,41 @@ pub fn peephole_optimizations(block: &mut BasicBlock) -> bool {
                 // The fact that it cannot be proven that any given indirect tile address does not point to
                 // itself means that we cannot optimize out the COPYFROM instruction in that case.
                 // A nearly identical argument also holds for the BUMPUP and BUMPDN cases.
-                to_remove.push(i+1);
+                remarks.transformation(lines, format!("removed {second} right after {first}, since the value is already in hands"));
+                to_remove.push((i+1, Some(i)));
             },
             [Add(a), Sub(b)] | [Sub(a), Add(b)]
             if a == b => { // adding and subtracting the same number
-                to_remove.push(i);
-                to_remove.push(i+1);
+                remarks.transformation(lines, format!("removed {first} / {second} pair, which cancel out"));
+                to_remove.push((i, None));
+                to_remove.push((i+1, None));
             },
             [BumpUp(Address::Direct(a)), BumpDn(Address::Direct(b))] |
             [BumpDn(Address::Direct(a)), BumpUp(Address::Direct(b))]
             if a == b => { // bumping up and down the same address
-                // NOTE: only direct addresses apply here for the same reason as above
-                to_remove.push(i);
-                to_remove.push(i+1);
+                // NOTE: only direct addresses apply here for the same reason as above.
+                //       the tile ends up the same, but the pair still leaves the tile's value in hands
+                remarks.transformation(lines, format!("replaced {first} / {second} pair with COPYFROM {a}, since they cancel out"));
+                to_replace.push((i, CopyFrom(Address::Direct(*a))));
+                to_remove.push((i+1, Some(i)));
             }
             _ => {},
         }
     }
     
     // make sure to remove duplicates (even if unlikely)
-    to_remove.dedup();
+    to_remove.dedup_by_key(|(i, _)| *i);
+    
+    for (i, instruction) in to_replace.iter().cloned() {
+        block.instructions[i] = instruction;
+    }
     
-    for &i in to_remove.iter().rev() {
-        block.instructions.remove(i);
+    for &(i, into) in to_remove.iter().rev() {
+        match into {
+            Some(into) => block.merge_instruction_into(i, into),
+            None => block.remove_instruction(i),
+        };
     }
     
-    to_remove.len() > 0
+    !to_remove.is_empty() || !to_replace.is_empty()
 }
@@ -0,0 +1,117 @@
+//! optimizations that work on whole loops (see [`structure`](super::structure)).
+
+use crate::instruction::Instruction;
+
+use super::{
+    basic_blocks::{BasicBlock, BasicBlockId},
+    block_layout::{best_layout_cost, best_layout_jumps, OptimizationGoal},
+    control_flow_graph::{Optimization, ProgramControlFlowGraph},
+    jump_flag::JumpFlag,
+};
+
+/// how much faster a rotated loop has to be (in estimated steps per run) for it to be worth it.
+const EPSILON: f64 = 1e-9;
+
+/// the average number of jumps that run on the way from the end of `latch`, through the test at the
+/// end of `test`, and back into the loop (i.e. on every trip around the loop except the last one),
+/// with `jumps` being the jump instructions at the end of each block.
+fn jumps_per_trip(graph: &ProgramControlFlowGraph, jumps: &[Vec<(Instruction, BasicBlockId)>], latch: usize, test: usize, body: &[BasicBlockId]) -> f64 {
+    let mut total = 0.0;
+    let mut trips = 0.0;
+    
+    for sign in [JumpFlag::IfNegative, JumpFlag::IfZero, JumpFlag::IfPositive] {
+        let targets = graph.blocks[test].effective_outgoing_jumps();
+        let Some((target, _)) = targets.iter().find(|(_, flag)| flag.contains(sign)) else { continue };
+        if !body.contains(target) { continue }
+        
+        // (every jump runs until one of them is taken, or the block falls through)
+        let taken = jumps[test].iter().position(|(jump, _)| match jump {
+            Instruction::JumpZ(_) => sign == JumpFlag::IfZero,
+            Instruction::JumpN(_) => sign == JumpFlag::IfNegative,
+            _ => true,
+        });
+        total += taken.map_or(jumps[test].len(), |k| k + 1) as f64;
+        trips += 1.0;
+    }
+    
+    jumps[latch].len() as f64 + if trips > 0.0 { total / trips } else { 0.0 }
+}
+
+/// turns loops that test at the top into loops that test at the bottom.
+///
+/// a loop whose header decides whether to keep going has to jump back up to it at the end
+/// of every iteration, and then (usually) jump again to get past the test. instead, the latch
+/// can fall through into a copy of the header, which jumps straight back into the body, and the
+/// original header is only left to decide whether to run the loop at all.
+///
+/// sometimes the same thing can be done by just moving the header below the rest of the loop
+/// (and jumping to it when the loop starts, e.g. with a `JUMP` at the very start of the program),
+/// which [`layout_blocks`](super::block_layout::layout_blocks) already does. so a header only gets
+/// copied if that makes every trip around the loop through the latch run fewer jumps (and not just
+/// the last one, which leaves the loop), and the steps that the best layout is estimated to take go
+/// down too. it never happens when optimizing for size, since the copy makes the program bigger.
+pub fn rotate_loops(goal: OptimizationGoal) -> impl Optimization {
+    move |graph: &mut ProgramControlFlowGraph| {
+        if goal == OptimizationGoal::Size { return false }
+        
+        let loops = graph.structure().loops.clone();
+        let before = best_layout_cost(graph, goal).0;
+        let jumps_before = best_layout_jumps(graph, goal);
+        
+        for l in loops.iter() {
+            let Some(h) = graph.blocks.iter().position(|block| block.id == l.header) else { continue };
+            
+            // (a header that doesn't test anything is just part of the loop, and `merge-blocks` deals with it)
+            if graph.blocks[h].effective_outgoing_jumps().len() < 2 { continue }
+            
+            for latch in l.latches.iter().filter(|latch| **latch != l.header) {
+                let Some(i) = graph.blocks.iter().position(|block| block.id == *latch) else { continue };
+                if graph.blocks[i].effective_outgoing_jumps() != [(l.header.clone(), JumpFlag::Always)] { continue }
+                
+                // (new blocks need an id that isn't used yet, including by the jumps to the end of the program)
+                let id = graph.blocks.iter()
+                    .flat_map(|block| std::iter::once(block.id.0).chain(block.outgoing_jumps.iter().map(|(id, _)| id.0)))
+                    .max().unwrap_or(0) + 1;
+                
+                let trip_before = jumps_per_trip(graph, &jumps_before, i, h, &l.blocks);
+                
+                let header = &graph.blocks[h];
+                let copy = BasicBlock {
+                    id: BasicBlockId(id),
+                    instructions: header.instructions.clone(),
+                    outgoing_jumps: header.outgoing_jumps.clone(),
+                    incoming_jumps: Vec::new(),
+                    comments: Vec::new(),
+                    source_lines: header.source_lines.clone(),
+                    jump_lines: header.jump_lines.clone(),
+                };
+                
+                let original_jumps = std::mem::replace(&mut graph.blocks[i].outgoing_jumps, vec![(BasicBlockId(id), JumpFlag::Always)]);
+                graph.blocks.insert(i + 1, copy);
+                // (so the costing sees the jumps into the copy, and not the loops from before it)
+                graph.refresh_incoming_jumps();
+                graph.invalidate_structure();
+                
+                let after = best_layout_cost(graph, goal).0;
+                let trip_after = jumps_per_trip(graph, &best_layout_jumps(graph, goal), i, i + 1, &l.blocks);
+                if after < before - EPSILON && trip_after < trip_before - EPSILON {
+                    let header = &graph.blocks[if h > i { h + 1 } else { h }];
+                    let lines = header.lines(0..header.instructions.len());
+                    graph.remarks.transformation(lines, format!(
+                        "rotated the loop at block {}, so block {} runs a copy of its test (block {id}) instead of jumping back up to it, for about {:.2} fewer steps per run",
+                        l.header.0, latch.0, before - after,
+                    ));
+                    return true;
+                }
+                
+                // (it didn't help, so put everything back the way it was)
+                graph.blocks.remove(i + 1);
+                graph.blocks[i].outgoing_jumps = original_jumps;
+                graph.refresh_incoming_jumps();
+                graph.invalidate_structure();
+            }
+        }
+        
+        false
+    }
+}
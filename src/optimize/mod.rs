@@ -3,3 +3,15 @@ pub mod basic_blocks;
 pub mod control_flow_graph;
 pub mod block_optimizations;
 pub mod local_optimizations;
+pub mod dataflow;
+pub mod liveness;
+pub mod constants;
+pub mod ranges;
+pub mod global_optimizations;
+pub mod loop_optimizations;
+pub mod block_layout;
+pub mod pass_manager;
+pub mod remarks;
+pub mod dot;
+pub mod ir;
+pub mod structure;
@@ -0,0 +1,191 @@
+//! running a pipeline of optimization passes until none of them can improve the program anymore.
+
+use super::{
+    block_layout::{layout_blocks, OptimizationGoal},
+    block_optimizations::{combine_sequential_blocks, merge_tails, remove_dead_blocks, remove_empty_blocks, thread_jumps},
+    control_flow_graph::{Optimization, ProgramControlFlowGraph},
+    global_optimizations::{narrow_jumps, propagate_constants, remove_dead_stores},
+    local_optimizations::{local_optimization, peephole_optimizations, simplify_outgoing_jumps},
+    loop_optimizations::rotate_loops,
+};
+
+/// every pass that can be put in a pipeline by name, in the order the default pipeline runs them.
+pub const PASSES: &[(&str, &str)] = &[
+    ("simplify-jumps", "make the jumps out of each block as simple as possible"),
+    ("dce", "remove blocks that can never be reached"),
+    ("merge-blocks", "merge blocks that always run one after the other"),
+    ("empty-blocks", "remove blocks without any instructions, by jumping straight past them"),
+    ("merge-tails", "move instructions that every block jumping to a block ends with into that block"),
+    ("peephole", "remove and simplify redundant instructions within blocks"),
+    ("dse", "remove COPYTOs to tiles that never get read again"),
+    ("constprop", "use the values that are known at compile time (e.g. from the initial floor) to simplify instructions and jumps"),
+    ("ranges", "narrow down the conditions on jumps, using the range of values that could be in hands"),
+    ("thread-jumps", "make jumps skip tests whose outcome is already known from the jump itself, by copying small blocks"),
+    ("rotate-loops", "copy the test at the top of a loop to the bottom of it, when that needs fewer jumps per iteration"),
+    ("layout", "reorder the blocks to need as few jumps as possible"),
+];
+
+/// creates the pass with the given name (see [`PASSES`]).
+fn create_pass(name: &str, goal: OptimizationGoal) -> Option<Box<dyn Optimization>> {
+    Some(match name {
+        "simplify-jumps" => Box::new(local_optimization(simplify_outgoing_jumps)),
+        "dce" => Box::new(remove_dead_blocks),
+        "merge-blocks" => Box::new(combine_sequential_blocks),
+        "empty-blocks" => Box::new(remove_empty_blocks),
+        "merge-tails" => Box::new(merge_tails),
+        "peephole" => Box::new(local_optimization(peephole_optimizations)),
+        "dse" => Box::new(remove_dead_stores),
+        "constprop" => Box::new(propagate_constants),
+        "ranges" => Box::new(narrow_jumps),
+        "thread-jumps" => Box::new(thread_jumps(goal)),
+        "rotate-loops" => Box::new(rotate_loops(goal)),
+        "layout" => Box::new(layout_blocks(goal)),
+        _ => return None,
+    })
+}
+
+/// a pass name that isn't in [`PASSES`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownPass(pub String);
+
+impl std::fmt::Display for UnknownPass {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "there's no pass called `{}` (the passes are:", self.0)?;
+        for (name, _) in PASSES {
+            write!(f, " {name}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl std::error::Error for UnknownPass {}
+
+/// what happened while running a pipeline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PassStatistics {
+    /// how many times each pass changed the graph, in pipeline order
+    pub runs: Vec<(&'static str, usize)>,
+    
+    /// false if the pipeline hit its iteration limit before the passes stopped changing things
+    pub converged: bool,
+}
+
+impl PassStatistics {
+    /// the total number of times any pass changed the graph.
+    pub fn total(&self) -> usize {
+        self.runs.iter().map(|(_, count)| count).sum()
+    }
+}
+
+impl std::fmt::Display for PassStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        for (name, count) in self.runs.iter() {
+            writeln!(f, "{name:<16} {count:>5}")?;
+        }
+        write!(f, "{:<16} {:>5}", "total", self.total())?;
+        if !self.converged {
+            write!(f, " (gave up before reaching a fix point)")?;
+        }
+        Ok(())
+    }
+}
+
+/// an ordered pipeline of named optimization passes.
+///
+/// the pipeline starts over from the first pass every time a pass changes something, so a
+/// pass only runs once every pass before it has nothing left to do. this is why cheap cleanup
+/// passes should go first, and expensive passes that only need to run once (like `layout`) last.
+pub struct PassManager {
+    passes: Vec<(&'static str, Box<dyn Optimization>)>,
+    
+    /// the maximum number of times passes can change the graph, in case some of them keep undoing each other
+    pub iteration_limit: usize,
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PassManager {
+    /// an empty pipeline, which doesn't change anything (i.e. `-O0`).
+    pub fn new() -> Self {
+        Self { passes: Vec::new(), iteration_limit: 10_000 }
+    }
+    
+    /// every pass, in the order of [`PASSES`] (i.e. `-Os` or `-Ospeed`, depending on `goal`).
+    pub fn preset(goal: OptimizationGoal) -> Self {
+        let names: Vec<_> = PASSES.iter().map(|(name, _)| *name).collect();
+        Self::from_names(&names, goal).unwrap()
+    }
+    
+    /// a pipeline of the passes with the given names (see [`PASSES`]), in that order.
+    pub fn from_names(names: &[&str], goal: OptimizationGoal) -> Result<Self, UnknownPass> {
+        let mut manager = Self::new();
+        for name in names {
+            let (name, _) = PASSES.iter().find(|(pass, _)| pass == name)
+                .ok_or_else(|| UnknownPass(name.to_string()))?;
+            manager.add_pass(name, create_pass(name, goal).unwrap());
+        }
+        Ok(manager)
+    }
+    
+    /// adds a pass to the end of the pipeline.
+    pub fn add_pass(&mut self, name: &'static str, pass: Box<dyn Optimization>) -> &mut Self {
+        self.passes.push((name, pass));
+        self
+    }
+    
+    /// the names of the passes in the pipeline, in order.
+    pub fn pass_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.passes.iter().map(|(name, _)| *name)
+    }
+    
+    /// runs the pipeline on the graph until none of the passes change anything.
+    pub fn run(&mut self, graph: &mut ProgramControlFlowGraph) -> PassStatistics {
+        self.run_with(graph, |_, _| Ok::<_, std::convert::Infallible>(())).unwrap()
+    }
+    
+    /// same as [`PassManager::run`], but calls `after_pass` with the name of each pass that changed
+    /// the graph, right after it ran (e.g. to log it, or to check that it didn't break anything),
+    /// which can stop the whole thing early.
+    pub fn run_with<E>(
+        &mut self,
+        graph: &mut ProgramControlFlowGraph,
+        mut after_pass: impl FnMut(&'static str, &ProgramControlFlowGraph) -> Result<(), E>,
+    ) -> Result<PassStatistics, E> {
+        let mut counts = vec![0; self.passes.len()];
+        let mut converged = true;
+        
+        'pipeline: loop {
+            for (i, (name, pass)) in self.passes.iter_mut().enumerate() {
+                let remarks_before = graph.remarks.0.len();
+                let modified = graph.run_optimization_pass(|graph: &mut ProgramControlFlowGraph| pass.optimize(graph));
+                for remark in graph.remarks.0[remarks_before..].iter_mut() {
+                    remark.pass = name;
+                }
+                
+                if modified {
+                    counts[i] += 1;
+                    after_pass(name, graph)?;
+                    
+                    if counts.iter().sum::<usize>() >= self.iteration_limit {
+                        converged = false;
+                        break 'pipeline;
+                    }
+                    continue 'pipeline;
+                }
+            }
+            
+            break;
+        }
+        
+        graph.relabel_blocks();
+        
+        Ok(PassStatistics {
+            runs: self.pass_names().zip(counts).collect(),
+            converged,
+        })
+    }
+}
@@ -0,0 +1,349 @@
+//! value range analysis over the floor tiles and hands.
+//!
+//! every value is tracked as whether it could be empty, whether it could be a letter, and the
+//! range of numbers it could be. this is a lot less precise than knowing the exact value (see
+//! [`constants`](super::constants)), but it still knows e.g. that a tile that starts at 0 and only
+//! ever gets bumped up is never negative, even in a loop.
+
+use crate::{
+    datacube::DataCube,
+    instruction::{Address, Instruction},
+};
+
+use super::{
+    basic_blocks::{BasicBlock, BasicBlockId},
+    control_flow_graph::ProgramControlFlowGraph,
+    dataflow::{self, DataflowAnalysis, DataflowOptions, DataflowResult, Direction, Lattice},
+    jump_flag::JumpFlag,
+    liveness::Liveness,
+};
+
+const MIN: i32 = -999;
+const MAX: i32 = 999;
+
+/// the values that something could be.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Range {
+    pub empty: bool,
+    pub letter: bool,
+    
+    /// the smallest and largest number it could be (inclusive), or `None` if it can't be a number
+    pub numbers: Option<(i32, i32)>,
+}
+
+impl Range {
+    /// it can't be anything at all (i.e. the code is never reached).
+    pub const NOTHING: Range = Range { empty: false, letter: false, numbers: None };
+    
+    /// it's definitely empty.
+    pub const EMPTY: Range = Range { empty: true, letter: false, numbers: None };
+    
+    /// it could be any value (but not empty).
+    pub const ANY_CUBE: Range = Range { empty: false, letter: true, numbers: Some((MIN, MAX)) };
+    
+    pub fn number(n: i32) -> Self {
+        Range { empty: false, letter: false, numbers: Some((n, n)) }
+    }
+    
+    pub fn from_tile(tile: Option<&DataCube>) -> Self {
+        match tile {
+            None => Range::EMPTY,
+            Some(DataCube::Number(n)) => Range::number(*n as i32),
+            Some(DataCube::Letter(_)) => Range { empty: false, letter: true, numbers: None },
+        }
+    }
+    
+    pub fn is_nothing(&self) -> bool {
+        *self == Range::NOTHING
+    }
+    
+    /// the same range, minus the possibility of being empty.
+    pub fn without_empty(&self) -> Self {
+        Range { empty: false, ..self.clone() }
+    }
+    
+    /// the numbers in the range, limited to `lo..=hi` (which might leave nothing).
+    fn clamp_numbers(numbers: Option<(i32, i32)>, lo: i32, hi: i32) -> Option<(i32, i32)> {
+        numbers.map(|(a, b)| (a.max(lo), b.min(hi))).filter(|(a, b)| a <= b)
+    }
+    
+    /// the signs that a (non-empty) value in the range could have, as far as the jumps are concerned.
+    /// (letters are neither zero nor negative, so they count as positive)
+    pub fn signs(&self) -> JumpFlag {
+        let mut signs = JumpFlag::Never;
+        if let Some((lo, hi)) = self.numbers {
+            if lo < 0 { signs |= JumpFlag::IfNegative }
+            if lo <= 0 && hi >= 0 { signs |= JumpFlag::IfZero }
+            if hi > 0 { signs |= JumpFlag::IfPositive }
+        }
+        if self.letter {
+            signs |= JumpFlag::IfPositive;
+        }
+        signs
+    }
+    
+    /// the part of the range that a jump with the given flag would be taken for.
+    /// (a conditional jump on empty hands is an error, so that's never part of it)
+    pub fn refine(&self, flag: JumpFlag) -> Self {
+        // (an unconditional jump doesn't look at hands at all, so they might still be empty after it)
+        if flag == JumpFlag::Always {
+            return self.clone();
+        }
+        
+        let parts = [
+            (JumpFlag::IfNegative, Self::clamp_numbers(self.numbers, MIN, -1)),
+            (JumpFlag::IfZero, Self::clamp_numbers(self.numbers, 0, 0)),
+            (JumpFlag::IfPositive, Self::clamp_numbers(self.numbers, 1, MAX)),
+        ];
+        
+        let mut result = Range { empty: false, letter: self.letter && flag.contains(JumpFlag::IfPositive), numbers: None };
+        for (sign, numbers) in parts {
+            if flag.contains(sign) {
+                result.join(&Range { numbers, ..Range::NOTHING });
+            }
+        }
+        result
+    }
+    
+    fn join(&mut self, other: &Self) {
+        self.empty |= other.empty;
+        self.letter |= other.letter;
+        self.numbers = match (self.numbers, other.numbers) {
+            (Some((a, b)), Some((c, d))) => Some((a.min(c), b.max(d))),
+            (a, b) => a.or(b),
+        };
+    }
+    
+    /// any bound that's still moving jumps straight to the end of its range.
+    fn widen(&mut self, previous: &Self) {
+        if let (Some((lo, hi)), Some((previous_lo, previous_hi))) = (self.numbers, previous.numbers) {
+            self.numbers = Some((
+                if lo < previous_lo { MIN } else { lo },
+                if hi > previous_hi { MAX } else { hi },
+            ));
+        }
+    }
+}
+
+/// everything that's known at some point in the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct State {
+    pub hands: Range,
+    pub floor: Vec<Range>,
+}
+
+/// what an arithmetic instruction could result in.
+pub struct Arithmetic {
+    /// the numbers it could result in, before checking for overflow
+    pub numbers: Option<(i32, i32)>,
+    
+    /// true if it could result in a number without overflowing (e.g. subtracting two letters)
+    pub letters: bool,
+}
+
+impl Arithmetic {
+    /// true if the instruction overflows whenever it doesn't fail in some other way.
+    pub fn always_overflows(&self) -> bool {
+        !self.letters && self.numbers.is_some_and(|(lo, hi)| hi < MIN || lo > MAX)
+    }
+    
+    /// the values it could result in, without the ones that overflow.
+    fn result(&self) -> Range {
+        let mut result = Range { numbers: Range::clamp_numbers(self.numbers, MIN, MAX), ..Range::NOTHING };
+        if self.letters {
+            result.join(&Range { numbers: Some((-25, 25)), ..Range::NOTHING });
+        }
+        result
+    }
+}
+
+impl State {
+    /// every tile that an address could point to.
+    pub fn targets(&self, address: &Address) -> Vec<usize> {
+        let last = self.floor.len() as i32 - 1;
+        match address {
+            Address::Direct(a) => (*a < self.floor.len()).then_some(*a).into_iter().collect(),
+            Address::Indirect(a) => match self.floor.get(*a).and_then(|tile| Range::clamp_numbers(tile.numbers, 0, last)) {
+                Some((lo, hi)) => (lo as usize..=hi as usize).collect(),
+                None => Vec::new(),
+            },
+        }
+    }
+    
+    /// true if an address could point past the tiles that are tracked. (this can only happen with an
+    /// indirect address when the floor size isn't known, or it would be a runtime error)
+    pub fn untracked(&self, address: &Address) -> bool {
+        let len = self.floor.len();
+        match address {
+            Address::Direct(a) => *a >= len,
+            Address::Indirect(a) => self.floor.get(*a).is_none_or(|tile| tile.numbers.is_some_and(|(_, hi)| hi >= len as i32)),
+        }
+    }
+    
+    /// the values on every tile that an address could point to.
+    fn tile(&self, address: &Address) -> Range {
+        if self.untracked(address) {
+            return Range { empty: true, ..Range::ANY_CUBE };
+        }
+        let mut range = Range::NOTHING;
+        for tile in self.targets(address) {
+            range.join(&self.floor[tile]);
+        }
+        range
+    }
+    
+    /// what an `ADD`, `SUB`, `BUMPUP` or `BUMPDN` could result in.
+    pub fn arithmetic(&self, instruction: &Instruction) -> Option<Arithmetic> {
+        let (numbers, letters) = match instruction {
+            Instruction::Add(a) => {
+                let tile = self.tile(a);
+                (self.hands.numbers.zip(tile.numbers).map(|((a, b), (c, d))| (a + c, b + d)), false)
+            },
+            Instruction::Sub(a) => {
+                let tile = self.tile(a);
+                (self.hands.numbers.zip(tile.numbers).map(|((a, b), (c, d))| (a - d, b - c)), self.hands.letter && tile.letter)
+            },
+            Instruction::BumpUp(a) => (self.tile(a).numbers.map(|(lo, hi)| (lo + 1, hi + 1)), false),
+            Instruction::BumpDn(a) => (self.tile(a).numbers.map(|(lo, hi)| (lo - 1, hi - 1)), false),
+            _ => return None,
+        };
+        Some(Arithmetic { numbers, letters })
+    }
+    
+    /// writes `value` to every tile that an address could point to.
+    ///
+    /// (when it could point past the tracked tiles, it's treated as if it could point to any of them)
+    fn write(&mut self, address: &Address, value: &Range) {
+        if self.untracked(address) {
+            self.floor.iter_mut().for_each(|tile| tile.join(value));
+            return;
+        }
+        let targets = self.targets(address);
+        match targets[..] {
+            [tile] => self.floor[tile] = value.clone(),
+            _ => targets.into_iter().for_each(|tile| self.floor[tile].join(value)),
+        }
+    }
+    
+    /// updates the state to what it is after `instruction` runs, or returns false if it always fails.
+    pub fn transfer(&mut self, instruction: &Instruction) -> bool {
+        use Instruction::*;
+        
+        match instruction {
+            // (the program ends instead of reading from an empty inbox)
+            Inbox => self.hands = Range::ANY_CUBE,
+            Outbox => self.hands = Range::EMPTY,
+            CopyFrom(a) => self.hands = self.tile(a).without_empty(),
+            CopyTo(a) => {
+                let hands = self.hands.without_empty();
+                if hands.is_nothing() {
+                    return false;
+                }
+                self.write(a, &hands);
+            },
+            Add(_) | Sub(_) => self.hands = self.arithmetic(instruction).unwrap().result(),
+            BumpUp(a) | BumpDn(a) => {
+                let result = self.arithmetic(instruction).unwrap().result();
+                self.write(a, &result);
+                self.hands = result;
+            },
+            Jump(_) | JumpZ(_) | JumpN(_) => {},
+        }
+        
+        !self.hands.is_nothing()
+    }
+}
+
+/// `None` means the code is never reached.
+impl Lattice for Option<State> {
+    fn join(&mut self, other: &Self) {
+        match (self.as_mut(), other) {
+            (_, None) => {},
+            (None, Some(other)) => *self = Some(other.clone()),
+            (Some(state), Some(other)) => {
+                state.hands.join(&other.hands);
+                for (a, b) in state.floor.iter_mut().zip(other.floor.iter()) {
+                    a.join(b);
+                }
+            },
+        }
+    }
+    
+    fn widen(&mut self, previous: &Self) {
+        if let (Some(state), Some(previous)) = (self.as_mut(), previous) {
+            state.hands.widen(&previous.hands);
+            for (a, b) in state.floor.iter_mut().zip(previous.floor.iter()) {
+                a.widen(b);
+            }
+        }
+    }
+}
+
+/// the analysis itself, for [`dataflow::solve`].
+struct RangeAnalysis {
+    floor_size: usize,
+}
+
+impl DataflowAnalysis for RangeAnalysis {
+    type Fact = Option<State>;
+    
+    const DIRECTION: Direction = Direction::Forward;
+    
+    /// (if there's no initial floor, nothing is known about it)
+    fn boundary(&self, graph: &ProgramControlFlowGraph) -> Option<State> {
+        let floor = (0..self.floor_size)
+            .map(|tile| match graph.initial_floor.get(tile) {
+                _ if graph.initial_floor.is_empty() => Range { empty: true, ..Range::ANY_CUBE },
+                tile => Range::from_tile(tile.and_then(Option::as_ref)),
+            })
+            .collect();
+        
+        Some(State { hands: Range::EMPTY, floor })
+    }
+    
+    fn bottom(&self, _graph: &ProgramControlFlowGraph) -> Option<State> {
+        None
+    }
+    
+    fn transfer(&self, fact: &mut Option<State>, instruction: &Instruction) {
+        if fact.as_mut().is_some_and(|state| !state.transfer(instruction)) {
+            *fact = None;
+        }
+    }
+    
+    fn transfer_edge(&self, fact: &mut Option<State>, flag: JumpFlag) {
+        if let Some(state) = fact {
+            state.hands = state.hands.refine(flag);
+            if state.hands.is_nothing() {
+                *fact = None;
+            }
+        }
+    }
+}
+
+/// the ranges at the start and end of every block in a control flow graph.
+#[derive(Debug, Clone)]
+pub struct Ranges {
+    result: DataflowResult<Option<State>>,
+    floor_size: usize,
+}
+
+impl Ranges {
+    /// runs the analysis, or returns `None` if it doesn't reach a fix point.
+    pub fn compute(graph: &ProgramControlFlowGraph) -> Option<Self> {
+        let floor_size = Liveness::floor_size(graph);
+        let result = dataflow::solve(&RangeAnalysis { floor_size }, graph, DataflowOptions::default());
+        result.converged.then_some(Self { result, floor_size })
+    }
+    
+    /// the ranges right before each instruction in a block, and at the end of it.
+    /// (`None` if that point is never reached)
+    pub fn within_block(&self, block: &BasicBlock) -> Vec<Option<State>> {
+        self.result.within_block(&RangeAnalysis { floor_size: self.floor_size }, block)
+    }
+    
+    /// the ranges at the end of a block, before its jumps.
+    pub fn block_end(&self, block: &BasicBlockId) -> &Option<State> {
+        self.result.block_end(block)
+    }
+}
@@ -0,0 +1,115 @@
+//! optimization remarks: what each pass changed, where, and why.
+
+use crate::program::format_lines;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemarkKind {
+    /// the pass changed the program
+    Transformation,
+    
+    /// the pass noticed something wrong with the program (e.g. a guaranteed runtime error)
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remark {
+    /// the pass that made the remark (filled in by the [`PassManager`](super::pass_manager::PassManager))
+    pub pass: &'static str,
+    pub kind: RemarkKind,
+    
+    /// the lines in the original source that the remark is about (sorted, and possibly empty)
+    pub lines: Vec<usize>,
+    pub message: String,
+}
+
+/// an instruction as it would be written in a program (e.g. `ADD 3`).
+pub fn describe(instruction: &crate::instruction::Instruction) -> String {
+    instruction.to_string().split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+impl std::fmt::Display for Remark {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        write!(f, "[{}] ", self.pass)?;
+        if self.kind == RemarkKind::Warning {
+            f.write_str("warning: ")?;
+        }
+        f.write_str(&self.message)?;
+        if !self.lines.is_empty() {
+            write!(f, " ({})", format_lines(&self.lines))?;
+        }
+        Ok(())
+    }
+}
+
+/// every remark made while optimizing a program, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Remarks(pub Vec<Remark>);
+
+impl Remarks {
+    /// records that something in the program changed.
+    pub fn transformation(&mut self, lines: Vec<usize>, message: String) {
+        self.add(RemarkKind::Transformation, lines, message);
+    }
+    
+    /// records a problem with the program. (the same warning is only recorded once,
+    /// since passes can run on the same code over and over)
+    pub fn warning(&mut self, mut lines: Vec<usize>, message: String) {
+        lines.sort();
+        lines.dedup();
+        if !self.0.iter().any(|remark| remark.kind == RemarkKind::Warning && remark.lines == lines && remark.message == message) {
+            self.add(RemarkKind::Warning, lines, message);
+        }
+    }
+    
+    fn add(&mut self, kind: RemarkKind, mut lines: Vec<usize>, message: String) {
+        lines.sort();
+        lines.dedup();
+        self.0.push(Remark { pass: "", kind, lines, message });
+    }
+    
+    /// the remarks as a JSON array of objects with a `pass`, `kind`, `lines` and `message`.
+    pub fn to_json(&self) -> String {
+        fn string(s: &str) -> String {
+            let mut escaped = String::from("\"");
+            for c in s.chars() {
+                match c {
+                    '"' => escaped.push_str("\\\""),
+                    '\\' => escaped.push_str("\\\\"),
+                    '\n' => escaped.push_str("\\n"),
+                    c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+                    c => escaped.push(c),
+                }
+            }
+            escaped.push('"');
+            escaped
+        }
+        
+        let remarks: Vec<_> = self.0.iter()
+            .map(|remark| format!(
+                "  {{\"pass\": {}, \"kind\": {}, \"lines\": [{}], \"message\": {}}}",
+                string(remark.pass),
+                string(match remark.kind {
+                    RemarkKind::Transformation => "transformation",
+                    RemarkKind::Warning => "warning",
+                }),
+                remark.lines.iter().map(|line| line.to_string()).collect::<Vec<_>>().join(", "),
+                string(&remark.message),
+            ))
+            .collect();
+        
+        if remarks.is_empty() {
+            "[]\n".to_string()
+        } else {
+            format!("[\n{}\n]\n", remarks.join(",\n"))
+        }
+    }
+}
+
+impl std::fmt::Display for Remarks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        for remark in self.0.iter() {
+            writeln!(f, "{remark}")?;
+        }
+        Ok(())
+    }
+}
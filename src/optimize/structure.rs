@@ -0,0 +1,296 @@
+//! the structure of a control flow graph: which blocks dominate which, and the loops they make up.
+//!
+//! a block dominates another if every path from the entry block to it goes through the first one,
+//! and post-dominates it if every path from it to the end of the program does. a jump to a block
+//! that dominates it is a back edge, and closes a natural loop: the blocks that can reach the back
+//! edge without going through the block it jumps to (the loop's header).
+//!
+//! a block with an `INBOX` counts as jumping to the end of the program as well, since that's where
+//! the program ends once the inbox runs out. (otherwise, most programs would never end at all)
+
+use std::collections::{HashMap, HashSet};
+
+use crate::instruction::Instruction;
+
+use super::{
+    basic_blocks::BasicBlockId,
+    control_flow_graph::ProgramControlFlowGraph,
+};
+
+/// the immediate dominator of every node that can be reached from `root`, using the algorithm
+/// from "A Simple, Fast Dominance Algorithm" (Cooper, Harvey and Kennedy).
+///
+/// the root is its own immediate dominator, and nodes that can't be reached don't have one.
+fn immediate_dominators(successors: &[Vec<usize>], root: usize) -> Vec<Option<usize>> {
+    // number the nodes in reverse postorder, with an explicit stack since programs can be long
+    let mut postorder = Vec::new();
+    let mut visited = vec![false; successors.len()];
+    let mut stack = vec![(root, 0)];
+    visited[root] = true;
+    
+    while let Some((node, next)) = stack.last_mut() {
+        match successors[*node].get(*next) {
+            Some(&successor) => {
+                *next += 1;
+                if !visited[successor] {
+                    visited[successor] = true;
+                    stack.push((successor, 0));
+                }
+            },
+            None => {
+                postorder.push(*node);
+                stack.pop();
+            },
+        }
+    }
+    
+    let mut order = vec![usize::MAX; successors.len()];
+    for (i, &node) in postorder.iter().enumerate() {
+        order[node] = i;
+    }
+    
+    let mut predecessors = vec![Vec::new(); successors.len()];
+    for (node, targets) in successors.iter().enumerate().filter(|(node, _)| visited[*node]) {
+        for &target in targets {
+            predecessors[target].push(node);
+        }
+    }
+    
+    let mut idom = vec![None; successors.len()];
+    idom[root] = Some(root);
+    
+    let intersect = |idom: &[Option<usize>], mut a: usize, mut b: usize| {
+        while a != b {
+            while order[a] < order[b] { a = idom[a].unwrap() }
+            while order[b] < order[a] { b = idom[b].unwrap() }
+        }
+        a
+    };
+    
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &node in postorder.iter().rev().filter(|&&node| node != root) {
+            let new_idom = predecessors[node].iter()
+                .filter(|&&predecessor| idom[predecessor].is_some())
+                .fold(None, |new_idom, &predecessor| match new_idom {
+                    None => Some(predecessor),
+                    Some(other) => Some(intersect(&idom, predecessor, other)),
+                });
+            if new_idom.is_some() && idom[node] != new_idom {
+                idom[node] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    
+    idom
+}
+
+/// a dominator tree (or post-dominator tree) of the blocks in a graph.
+#[derive(Debug, Clone)]
+pub struct DominatorTree {
+    /// the immediate dominator of every block in the tree, or `None` for the blocks right below
+    /// the root. (the root is the entry block, or the end of the program for post-dominators)
+    parents: HashMap<BasicBlockId, Option<BasicBlockId>>,
+}
+
+impl DominatorTree {
+    /// true if the block is in the tree, i.e. it can be reached from the entry block
+    /// (or, for post-dominators, it can reach the end of the program).
+    pub fn contains(&self, block: &BasicBlockId) -> bool {
+        self.parents.contains_key(block)
+    }
+    
+    /// the closest block that dominates this one (other than itself), if there is one.
+    pub fn immediate_dominator(&self, block: &BasicBlockId) -> Option<&BasicBlockId> {
+        self.parents.get(block)?.as_ref()
+    }
+    
+    /// every block that dominates this one, starting with itself and going up the tree.
+    pub fn dominators(&self, block: &BasicBlockId) -> Vec<BasicBlockId> {
+        if !self.contains(block) {
+            return Vec::new();
+        }
+        std::iter::successors(Some(block.clone()), |block| self.immediate_dominator(block).cloned()).collect()
+    }
+    
+    /// true if `a` dominates `b`. (every block dominates itself)
+    pub fn dominates(&self, a: &BasicBlockId, b: &BasicBlockId) -> bool {
+        self.dominators(b).contains(a)
+    }
+}
+
+/// a natural loop, made up of every back edge to the same header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    /// the block that every run of the loop starts at (and that dominates the rest of the loop)
+    pub header: BasicBlockId,
+    
+    /// the blocks that jump back to the header
+    pub latches: Vec<BasicBlockId>,
+    
+    /// every block in the loop (including the header), in the order they get emitted in
+    pub blocks: Vec<BasicBlockId>,
+    
+    /// the ways out of the loop, as pairs of `(from, to)`, where `to` isn't a block if it's the
+    /// end of the program (either by jumping there, or by running out of inbox)
+    pub exits: Vec<(BasicBlockId, BasicBlockId)>,
+    
+    /// the index of the innermost loop that this one is inside of
+    pub parent: Option<usize>,
+    
+    /// how many loops this one is inside of, counting itself (so outermost loops have depth 1)
+    pub depth: usize,
+}
+
+/// the structural analyses of a graph (see the [module docs](self)).
+///
+/// this gets cached on the graph, see [`ProgramControlFlowGraph::structure`].
+#[derive(Debug, Clone)]
+pub struct Structure {
+    pub dominators: DominatorTree,
+    pub post_dominators: DominatorTree,
+    
+    /// the dominance frontier of every block that can be reached: the blocks where its dominance
+    /// stops (i.e. the ones it doesn't strictly dominate, but that a block it dominates jumps to)
+    pub frontiers: HashMap<BasicBlockId, Vec<BasicBlockId>>,
+    
+    /// the jumps to a block that dominates the block they're in, as pairs of `(from, to)`
+    pub back_edges: Vec<(BasicBlockId, BasicBlockId)>,
+    
+    /// the natural loops, in the order their headers get emitted in
+    pub loops: Vec<Loop>,
+}
+
+impl Structure {
+    pub fn compute(graph: &ProgramControlFlowGraph) -> Self {
+        let n = graph.blocks.len();
+        let id = |i: usize| graph.blocks[i].id.clone();
+        let indices: HashMap<_, _> = graph.blocks.iter().enumerate().map(|(i, block)| (block.id.clone(), i)).collect();
+        
+        // (node `n` is the end of the program)
+        let mut successors = vec![Vec::new(); n + 1];
+        for (i, block) in graph.blocks.iter().enumerate() {
+            let targets = block.effective_outgoing_jumps().into_iter()
+                .map(|(target, _)| indices.get(&target).copied().unwrap_or(n))
+                .chain(block.instructions.contains(&Instruction::Inbox).then_some(n));
+            for target in targets {
+                if !successors[i].contains(&target) {
+                    successors[i].push(target);
+                }
+            }
+        }
+        
+        let mut predecessors = vec![Vec::new(); n + 1];
+        for (i, targets) in successors.iter().enumerate() {
+            for &target in targets {
+                predecessors[target].push(i);
+            }
+        }
+        
+        let entry = indices.get(&BasicBlockId(0)).copied().unwrap_or(0);
+        let idom = match n {
+            0 => Vec::new(),
+            _ => immediate_dominators(&successors, entry),
+        };
+        let ipdom = immediate_dominators(&predecessors, n);
+        
+        // (the end of the program isn't a block, so the blocks right below it don't have a parent)
+        let tree = |idom: &[Option<usize>]| DominatorTree {
+            parents: (0..n)
+                .filter_map(|i| idom[i].map(|parent| (id(i), (parent != i && parent != n).then(|| id(parent)))))
+                .collect(),
+        };
+        let dominators = tree(&idom);
+        let post_dominators = tree(&ipdom);
+        
+        let reachable = |i: usize| i < n && idom[i].is_some();
+        
+        let mut frontiers: HashMap<_, Vec<_>> = (0..n).filter(|&i| reachable(i)).map(|i| (id(i), Vec::new())).collect();
+        for node in (0..n).filter(|&i| reachable(i)) {
+            let node_predecessors: Vec<_> = predecessors[node].iter().copied().filter(|&p| reachable(p)).collect();
+            // (the entry block is also where the program starts, so one jump to it is enough)
+            if node_predecessors.len() + usize::from(node == entry) < 2 { continue }
+            
+            // (the entry block doesn't have an immediate dominator, so the walk goes all the way up for it)
+            let stop = if node == entry { None } else { idom[node] };
+            for predecessor in node_predecessors {
+                let mut runner = Some(predecessor);
+                while let Some(block) = runner.filter(|_| runner != stop) {
+                    let frontier = frontiers.get_mut(&id(block)).unwrap();
+                    if !frontier.contains(&id(node)) {
+                        frontier.push(id(node));
+                    }
+                    runner = if block == entry { None } else { idom[block] };
+                }
+            }
+        }
+        
+        let back_edges: Vec<_> = (0..n)
+            .filter(|&i| reachable(i))
+            .flat_map(|i| successors[i].iter().map(move |&target| (i, target)))
+            .filter(|&(i, target)| target < n && dominators.dominates(&id(target), &id(i)))
+            .collect();
+        
+        // (like in the graph, the end of the program is an id that isn't used by any block)
+        let end = BasicBlockId(graph.blocks.iter().map(|block| block.id.0 + 1).max().unwrap_or(0));
+        
+        let mut loops = Vec::new();
+        for header in 0..n {
+            let latches: Vec<_> = back_edges.iter().filter(|(_, to)| *to == header).map(|(from, _)| *from).collect();
+            if latches.is_empty() { continue }
+            
+            // everything that can reach a latch without going through the header
+            let mut body = HashSet::from([header]);
+            let mut worklist = latches.clone();
+            while let Some(block) = worklist.pop() {
+                if body.insert(block) {
+                    worklist.extend(predecessors[block].iter().copied().filter(|&p| reachable(p)));
+                }
+            }
+            
+            let mut blocks: Vec<_> = body.iter().copied().collect();
+            blocks.sort();
+            
+            let mut exits = Vec::new();
+            for &i in blocks.iter() {
+                for &target in successors[i].iter().filter(|target| !body.contains(target)) {
+                    exits.push((id(i), if target == n { end.clone() } else { id(target) }));
+                }
+            }
+            
+            loops.push(Loop {
+                header: id(header),
+                latches: latches.into_iter().map(id).collect(),
+                blocks: blocks.into_iter().map(id).collect(),
+                exits,
+                parent: None,
+                depth: 1,
+            });
+        }
+        
+        // a loop is inside of another one if its header is (and the innermost one is the smallest)
+        for i in 0..loops.len() {
+            loops[i].parent = (0..loops.len())
+                .filter(|&j| j != i && loops[j].blocks.contains(&loops[i].header))
+                .min_by_key(|&j| loops[j].blocks.len());
+        }
+        for i in 0..loops.len() {
+            loops[i].depth = std::iter::successors(Some(i), |&j| loops[j].parent).count();
+        }
+        
+        Self {
+            dominators,
+            post_dominators,
+            frontiers,
+            back_edges: back_edges.into_iter().map(|(from, to)| (id(from), id(to))).collect(),
+            loops,
+        }
+    }
+    
+    /// how many loops a block is inside of (0 if it isn't in one).
+    pub fn loop_depth(&self, block: &BasicBlockId) -> usize {
+        self.loops.iter().filter(|l| l.blocks.contains(block)).count()
+    }
+}
@@ -1,57 +1,197 @@
 use std::collections::HashMap;
 
-use crate::{errors::{HRMRuntimeError, AsmParseError}, instruction::Instruction, datacube::DataCube};
+use crate::{
+    errors::{HRMRuntimeError, HRMRuntimeFault, AsmParseError, AsmDiagnostic, AsmParseErrors},
+    instruction::{Instruction, Address},
+    datacube::DataCube,
+};
 
 
+/// a drawing defined at the end of a program (i.e. a `DEFINE COMMENT n` or `DEFINE LABEL n` block).
+/// 
+/// `data` is the raw base64-encoded drawing data, without any line breaks or the final `;`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Drawing {
+    /// the drawing shown for every `COMMENT {index}` in the program.
+    Comment { index: usize, data: String },
+    
+    /// the label drawn on the floor tile with address `tile`.
+    Label { tile: usize, data: String },
+}
+
+/// a `COMMENT n` line in a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Comment {
+    /// the index of the instruction that the comment is placed right before
+    pub line: usize,
+    
+    /// which drawing it shows (see [`Drawing::Comment`])
+    pub index: usize,
+    
+    /// true if it comes before the labels that point to the same instruction, instead of after them
+    pub before_label: bool,
+}
+
+/// a set of line numbers in a source file.
+pub type SourceLines = std::collections::BTreeSet<usize>;
+
+/// formats lines like `line 7`, `lines 7-8` or `lines 3, 7, 9`.
+pub fn format_lines<'a>(lines: impl IntoIterator<Item = &'a usize>) -> String {
+    let lines: Vec<_> = lines.into_iter().copied().collect();
+    match lines[..] {
+        [] => String::new(),
+        [line] => format!("line {line}"),
+        [first, .., last] if last - first + 1 == lines.len() => format!("lines {first}-{last}"),
+        _ => format!("lines {}", lines.iter().map(|line| line.to_string()).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+/// programs that run for longer than this on a single inbox are assumed to be stuck.
+/// (none of the levels need anywhere near this many steps)
+pub const STEP_LIMIT: usize = 100_000;
+
+/// a program, as written in the game.
+#[derive(Debug, Clone)]
 pub struct Program {
     pub instructions: Vec<Instruction>,
+    
+    /// the floor at the start of the level (which isn't part of the assembly, so it starts out empty)
     pub initial_floor: Vec<Option<DataCube>>,
+    
+    /// the index of the instruction that each label points to
     pub jump_label_lines: std::collections::HashMap<String, usize>,
+    
+    /// the `COMMENT n` lines in the program, in order.
+    pub comments: Vec<Comment>,
+    pub drawings: Vec<Drawing>,
+    
+    /// the lines in the original source file that each instruction came from.
+    /// (an optimized instruction can come from more than one line, or from none at all,
+    /// and this is empty if the program didn't come from a file)
+    pub source_lines: Vec<SourceLines>,
 }
 
 impl Program {
-    pub fn from_asm(asm: &str) -> Result<Self, AsmParseError> {
-        let mut lines = asm.lines();
+    /// parses a program from the game's assembly format.
+    /// 
+    /// this doesn't stop at the first error, and instead returns every error in the file.
+    pub fn from_asm(asm: &str) -> Result<Self, AsmParseErrors> {
+        let mut lines = asm.lines().enumerate().map(|(i, line)| (i + 1, line));
         
-        if let Some(line) = lines.next() {
-            if line.trim() != "-- HUMAN RESOURCE MACHINE PROGRAM --" {
-                return Err(AsmParseError::MissingHeader);
-            }
-        } else {
-            return Err(AsmParseError::EmptyFile);
+        match lines.next() {
+            Some((_, line)) if line.trim() == "-- HUMAN RESOURCE MACHINE PROGRAM --" => {},
+            Some((n, line)) => return Err(AsmParseErrors(vec![
+                AsmDiagnostic::new(AsmParseError::MissingHeader, n, line, 0..line.len())
+            ])),
+            None => return Err(AsmParseErrors(vec![
+                AsmDiagnostic::new(AsmParseError::EmptyFile, 1, "", 0..0)
+            ])),
         }
         
+        let mut errors = Vec::new();
+        
         let mut label_lines = HashMap::<String, usize>::new();
         
         let mut instructions = Vec::new();
+        let mut comments = Vec::new();
+        let mut drawings = Vec::new();
         
-        for line in lines {
+        // where each instruction's argument is in the source, so that bad jumps can be reported
+        let mut argument_locations = Vec::new();
+        
+        while let Some((line_number, source_line)) = lines.next() {
             // strip comments
-            let line = line.split("--").next().unwrap_or("");
+            let line = source_line.split("--").next().unwrap_or("");
+            
+            // tokenize (very basic), keeping track of where each token is in the line
+            let spans: Vec<_> = line.split_whitespace()
+                .map(|tok| {
+                    let start = tok.as_ptr() as usize - line.as_ptr() as usize;
+                    start..start + tok.len()
+                })
+                .collect();
+            let tokens: Vec<_> = spans.iter().map(|span| &line[span.clone()]).collect();
+            let end_of_line = line.trim_end().len()..line.trim_end().len() + 1;
             
-            // tokenize (very basic)
-            let tokens: Vec<_> = line.split_whitespace().collect();
+            let error_at = |error, span: &std::ops::Range<usize>| {
+                AsmDiagnostic::new(error, line_number, source_line, span.clone())
+            };
             
-            // TODO: this is a shitty hack
-            if let Some(&define) = tokens.get(0) { 
-                if define == "DEFINE" { break }
-                if define == "COMMENT" { continue }
+            match tokens[..] {
+                ["DEFINE", kind, index] => {
+                    // (always read the data, so that it doesn't get parsed as instructions)
+                    let data = match Self::parse_drawing_data(&mut lines) {
+                        Ok(data) => data,
+                        Err(err) => {
+                            errors.push(error_at(err, &end_of_line));
+                            break;
+                        },
+                    };
+                    let index = match index.parse() {
+                        Ok(index) => index,
+                        Err(err) => {
+                            errors.push(error_at(AsmParseError::IntParseError(err), &spans[2]));
+                            continue;
+                        },
+                    };
+                    match kind {
+                        "COMMENT" => drawings.push(Drawing::Comment { index, data }),
+                        "LABEL" => drawings.push(Drawing::Label { tile: index, data }),
+                        _ => errors.push(error_at(AsmParseError::UnexpectedToken(kind.to_string()), &spans[1])),
+                    }
+                    continue;
+                },
+                ["DEFINE", _, _, extra, ..] => {
+                    errors.push(error_at(AsmParseError::UnexpectedToken(extra.to_string()), &spans[3]));
+                    continue;
+                },
+                ["DEFINE", ..] => {
+                    errors.push(error_at(AsmParseError::ExpectedToken("`COMMENT` or `LABEL`, and an index"), &end_of_line));
+                    continue;
+                },
+                ["COMMENT", index] => {
+                    match index.parse() {
+                        Ok(index) => comments.push(Comment { line: instructions.len(), index, before_label: false }),
+                        Err(err) => errors.push(error_at(AsmParseError::IntParseError(err), &spans[1])),
+                    }
+                    continue;
+                },
+                _ => {},
             }
             
             if let Some(tok) = tokens.get(2) {
                 // too many tokens on a line
-                return Err(AsmParseError::UnexpectedToken(tok.to_string()))
-            } else if let Some(&token) = tokens.get(0) {
+                errors.push(error_at(AsmParseError::UnexpectedToken(tok.to_string()), &spans[2]));
+            } else if let Some(&token) = tokens.first() {
                 // parse labels
                 if let Some(label) = token.strip_suffix(':') {
                     if let Some(arg) = tokens.get(1) {
-                        return Err(AsmParseError::UnexpectedToken(arg.to_string()))
+                        errors.push(error_at(AsmParseError::UnexpectedToken(arg.to_string()), &spans[1]));
+                        continue;
                     }
                     label_lines.insert(label.to_string(), instructions.len());
+                    
+                    // (any comments so far that are right before the same instruction come before this label)
+                    for comment in comments.iter_mut().filter(|comment: &&mut Comment| comment.line == instructions.len()) {
+                        comment.before_label = true;
+                    }
                 } else {
                     // parse normally
-                    let instruction = Instruction::parse_from_args(token, tokens.get(1).copied())?;
-                    instructions.push(instruction);
+                    match Instruction::parse_from_args(token, tokens.get(1).copied()) {
+                        Ok(instruction) => {
+                            instructions.push(instruction);
+                            argument_locations.push((line_number, source_line, spans.get(1).cloned()));
+                        },
+                        Err(err) => {
+                            let span = match (&err, spans.get(1)) {
+                                (AsmParseError::ExpectedToken(_), _) => &end_of_line,
+                                (AsmParseError::UnexpectedToken(tok), _) if tok == token => &spans[0],
+                                (_, Some(arg_span)) => arg_span,
+                                (_, None) => &spans[0],
+                            };
+                            errors.push(error_at(err, span));
+                        },
+                    }
                 }
             } else {
                 // empty line
@@ -60,191 +200,456 @@ impl Program {
         }
         
         // make sure all jumps work
-        Self::validate_jumps(&instructions, &label_lines)?;
+        for (i, err) in Self::validate_jumps(&instructions, &label_lines) {
+            let (line_number, source_line, span) = &argument_locations[i];
+            let span = span.clone().unwrap_or(0..source_line.len());
+            errors.push(AsmDiagnostic::new(err, *line_number, source_line, span));
+        }
+        
+        if !errors.is_empty() {
+            errors.sort_by_key(|err| (err.line, err.column));
+            return Err(AsmParseErrors(errors));
+        }
         
         Ok(Self {
-            instructions: instructions.into(),
+            instructions,
             initial_floor: Vec::new(),
             jump_label_lines: label_lines,
+            comments,
+            drawings,
+            source_lines: argument_locations.iter().map(|(line_number, _, _)| SourceLines::from([*line_number])).collect(),
         })
     }
     
-    fn validate_jumps(instructions: &[Instruction], labels: &HashMap<String, usize>) -> Result<(), AsmParseError> {
-        for instr in instructions {
-            match instr {
-                Instruction::Jump(label) | Instruction::JumpN(label) | Instruction::JumpZ(label) => {
-                    if !labels.contains_key(label) {
-                        return Err(AsmParseError::UnknownLabel(label.clone()));
-                    }
-                },
-                _ => {},
+    /// reads the base64 data of a `DEFINE` block, which can span multiple lines and ends with a `;`.
+    fn parse_drawing_data<'a>(lines: &mut impl Iterator<Item = (usize, &'a str)>) -> Result<String, AsmParseError> {
+        let mut data = String::new();
+        
+        for (_, line) in lines {
+            let line = line.trim();
+            if let Some(last) = line.strip_suffix(';') {
+                data.push_str(last);
+                return Ok(data);
             }
+            data.push_str(line);
         }
         
-        Ok(())
+        // the file ended before the block did
+        Err(AsmParseError::ExpectedToken("`;` at the end of the drawing data"))
     }
     
-    pub fn simulate(&self, mut inbox: Vec<DataCube>) -> Result<(usize, Vec<DataCube>), HRMRuntimeError> {
-        inbox.reverse(); // turn the inbox into a stack
+    /// finds every jump to a label that doesn't exist, as pairs of `(instruction index, error)`.
+    fn validate_jumps<'a>(instructions: &'a [Instruction], labels: &'a HashMap<String, usize>) -> impl Iterator<Item = (usize, AsmParseError)> + 'a {
+        instructions.iter().enumerate().filter_map(|(i, instr)| match instr {
+            Instruction::Jump(label) | Instruction::JumpN(label) | Instruction::JumpZ(label)
+            if !labels.contains_key(label) => {
+                Some((i, AsmParseError::UnknownLabel(label.clone())))
+            },
+            _ => None,
+        })
+    }
+    
+    /// serializes the program back into the game's assembly format.
+    ///
+    /// the output can be pasted directly into the game, and parsing it
+    /// again with [`Program::from_asm`] gives back the same program.
+    pub fn to_asm(&self) -> String {
+        self.write_asm(false)
+    }
+    
+    /// same as [`Program::to_asm`], but with a comment after each instruction saying which
+    /// lines of the original source it came from (see [`Program::source_lines`]).
+    pub fn to_asm_annotated(&self) -> String {
+        self.write_asm(true)
+    }
+    
+    fn write_asm(&self, annotate: bool) -> String {
+        use std::fmt::Write;
+        
+        // group labels by the line they point to (sorted, so the output is deterministic)
+        let mut labels_at = vec![Vec::new(); self.instructions.len() + 1];
+        for (label, &line) in self.jump_label_lines.iter() {
+            labels_at[line.min(self.instructions.len())].push(label.as_str());
+        }
+        for labels in labels_at.iter_mut() {
+            labels.sort();
+        }
+        
+        let mut asm = String::from("-- HUMAN RESOURCE MACHINE PROGRAM --\n\n");
+        
+        for (i, labels) in labels_at.iter().enumerate() {
+            let comments = |before_label| self.comments.iter()
+                .filter(move |comment| comment.line == i && comment.before_label == before_label);
+            
+            for comment in comments(true) {
+                writeln!(asm, "    {:<8} {}", "COMMENT", comment.index).unwrap();
+            }
+            for label in labels {
+                writeln!(asm, "{label}:").unwrap();
+            }
+            for comment in comments(false) {
+                writeln!(asm, "    {:<8} {}", "COMMENT", comment.index).unwrap();
+            }
+            if let Some(instruction) = self.instructions.get(i) {
+                match self.source_lines.get(i).filter(|lines| annotate && !lines.is_empty()) {
+                    Some(lines) => writeln!(asm, "    {:<16} -- {}", instruction.to_string(), format_lines(lines)).unwrap(),
+                    None => writeln!(asm, "    {instruction}").unwrap(),
+                }
+            }
+        }
         
-        let mut steps = 0;
-        let mut program_counter = 0;
-        let mut current_state = self.initial_floor.clone();
-        let mut held_item: Option<DataCube> = None;
-        let mut outbox = Vec::new();
+        asm.push('\n');
         
+        for drawing in self.drawings.iter() {
+            let (kind, index, data) = match drawing {
+                Drawing::Comment { index, data } => ("COMMENT", index, data),
+                Drawing::Label { tile, data } => ("LABEL", tile, data),
+            };
+            
+            // the game wraps the data at 80 columns
+            writeln!(asm, "\nDEFINE {kind} {index}").unwrap();
+            for (i, chunk) in data.as_bytes().chunks(80).enumerate() {
+                if i > 0 { asm.push('\n') }
+                asm.push_str(std::str::from_utf8(chunk).unwrap());
+            }
+            asm.push_str(";\n");
+        }
+        
+        asm
+    }
+    
+    /// generates the `n`th label name, in the same style as the game does.
+    /// (i.e. `a`, `b`, ..., `z`, `aa`, `ab`, ...)
+    pub fn label_name(mut n: usize) -> String {
+        let mut name = Vec::new();
         loop {
-            // reached end of program
-            if program_counter >= self.instructions.len() {
-                break;
+            name.push(b'a' + (n % 26) as u8);
+            if n < 26 { break }
+            n = n / 26 - 1;
+        }
+        name.reverse();
+        String::from_utf8(name).unwrap()
+    }
+    
+    /// runs the program on the given inbox, returning the number of steps it took and the outbox.
+    /// 
+    /// gives up with [`HRMRuntimeError::TooManySteps`] after [`STEP_LIMIT`] steps, so that a program
+    /// that never stops doesn't hang (use [`Program::simulate_with`] to run it without a limit).
+    pub fn simulate(&self, inbox: Vec<DataCube>) -> Result<(usize, Vec<DataCube>), Box<HRMRuntimeFault>> {
+        self.simulate_with(inbox, self.initial_floor.clone(), Some(STEP_LIMIT))
+    }
+    
+    /// same as [`Program::simulate`], but starting with a different floor, and giving up with
+    /// [`HRMRuntimeError::TooManySteps`] once `step_limit` steps have run (or never, for `None`).
+    pub fn simulate_with(&self, inbox: Vec<DataCube>, floor: Vec<Option<DataCube>>, step_limit: Option<usize>) -> Result<(usize, Vec<DataCube>), Box<HRMRuntimeFault>> {
+        let mut machine = Machine::new(inbox, floor);
+        
+        // (runs until the end of the program is reached)
+        while let Some(instruction) = self.instructions.get(machine.program_counter) {
+            let result = match step_limit {
+                Some(limit) if machine.steps >= limit => Err(HRMRuntimeError::TooManySteps { limit }),
+                _ => self.step(&mut machine, instruction),
+            };
+            
+            match result {
+                Ok(true) => {},
+                Ok(false) => break, // reached the end of the inbox
+                Err(error) => return Err(Box::new(HRMRuntimeFault {
+                    error,
+                    program_counter: machine.program_counter,
+                    instruction: instruction.clone(),
+                    address: machine.address,
+                    hands: machine.hands,
+                    floor: machine.floor,
+                    outbox: machine.outbox,
+                    steps: machine.steps,
+                    source_lines: self.source_lines.get(machine.program_counter).map(|lines| lines.iter().copied().collect()).unwrap_or_default(),
+                })),
             }
+        }
+        
+        Ok((machine.steps, machine.outbox))
+    }
+    
+    /// runs a single instruction. returns false if the program should stop.
+    fn step(&self, machine: &mut Machine, instruction: &Instruction) -> Result<bool, HRMRuntimeError> {
+        let operation = instruction.name();
+        machine.address = None;
+        
+        match instruction {
+            // IO instructions
+            Instruction::Inbox => {
+                if let Some(cube) = machine.inbox.pop() {
+                    machine.hands = Some(cube);
+                } else {
+                    return Ok(false);
+                }
+            },
+            Instruction::Outbox => {
+                if let Some(cube) = machine.hands.take() {
+                    machine.outbox.push(cube);
+                } else {
+                    return Err(HRMRuntimeError::EmptyHands { operation });
+                }
+            },
             
-            // println!("{} {program_counter}: {:?}", steps+1, self.instructions[program_counter]);
+            // copy instructions
+            Instruction::CopyFrom(a) => {
+                let floor_tile = machine.tile(a, operation)?;
+                
+                machine.hands = match floor_tile {
+                    Some(x) => Some(x.clone()),
+                    None => return Err(HRMRuntimeError::EmptyFloor { operation }),
+                };
+            },
+            Instruction::CopyTo(a) => {
+                let hands = machine.hands.clone();
+                let floor_tile = machine.tile_mut(a, operation)?;
+                
+                if hands.is_none() {
+                    return Err(HRMRuntimeError::EmptyHands { operation });
+                }
+                
+                *floor_tile = hands;
+            },
             
-            match &self.instructions[program_counter] {
-                // IO instructions
-                Instruction::Inbox => {
-                    if let Some(cube) = inbox.pop() {
-                        held_item = Some(cube);
-                    } else {
-                        break; // reached the end of the inbox
-                    }
-                },
-                Instruction::Outbox => {
-                    if let Some(cube) = held_item.take() {
-                        outbox.push(cube);
-                    } else {
-                        return Err(HRMRuntimeError::EmptyHands);
-                    }
-                },
+            // arithmetic instructions
+            Instruction::Add(a) => {
+                let hands = machine.hands.clone();
+                let floor_tile = machine.tile(a, operation)?;
                 
-                // copy instructions
-                Instruction::CopyFrom(a) => {
-                    let floor_tile = a.follow(&current_state)?;
-                    
-                    held_item = match floor_tile {
-                        Some(x) => Some(x.clone()),
-                        None => return Err(HRMRuntimeError::EmptyFloor),
-                    };
-                },
-                Instruction::CopyTo(a) => {
-                    let floor_tile = a.follow_mut(&mut current_state)?;
+                match (hands, floor_tile) {
+                    (None, _) => return Err(HRMRuntimeError::EmptyHands { operation }),
+                    (_, None) => return Err(HRMRuntimeError::EmptyFloor { operation }),
+                    (_, Some(DataCube::Letter(_))) | (Some(DataCube::Letter(_)), _)
+                        => return Err(HRMRuntimeError::LetterMath { operation }),
                     
-                    if held_item.is_none() {
-                        return Err(HRMRuntimeError::EmptyHands);
-                    }
-                    
-                    *floor_tile = held_item.clone();
-                },
+                    (Some(DataCube::Number(a)), Some(DataCube::Number(b))) => {
+                        machine.hands = Some(DataCube::from_number(a + *b)?);
+                    },
+                }
+            },
+            Instruction::Sub(a) => {
+                let hands = machine.hands.clone();
+                let floor_tile = machine.tile(a, operation)?;
                 
-                // arithmetic instructions
-                Instruction::Add(a) => {
-                    let floor_tile = a.follow(&current_state)?;
+                match (hands, floor_tile) {
+                    (None, _) => return Err(HRMRuntimeError::EmptyHands { operation }),
+                    (_, None) => return Err(HRMRuntimeError::EmptyFloor { operation }),
                     
-                    match (held_item, floor_tile) {
-                        (None, _) => return Err(HRMRuntimeError::EmptyHands),
-                        (_, None) => return Err(HRMRuntimeError::EmptyFloor),
-                        (_, Some(DataCube::Letter(_))) | (Some(DataCube::Letter(_)), _)
-                            => return Err(HRMRuntimeError::LetterMath),
-                        
-                        (Some(DataCube::Number(a)), Some(DataCube::Number(b))) => {
-                            held_item = Some(DataCube::from_number(a + *b)?);
-                        },
-                    }
-                },
-                Instruction::Sub(a) => {
-                    let floor_tile = a.follow(&current_state)?;
+                    // letter vs. number subtraction is not allowed...
+                    (Some(DataCube::Letter(_)), Some(DataCube::Number(_))) 
+                    | (Some(DataCube::Number(_)), Some(DataCube::Letter(_)))
+                        => return Err(HRMRuntimeError::LetterMath { operation }),
                     
-                    match (held_item, floor_tile) {
-                        (None, _) => return Err(HRMRuntimeError::EmptyHands),
-                        (_, None) => return Err(HRMRuntimeError::EmptyFloor),
-                        
-                        // letter vs. number subtraction is not allowed...
-                        (Some(DataCube::Letter(_)), Some(DataCube::Number(_))) 
-                        | (Some(DataCube::Number(_)), Some(DataCube::Letter(_)))
-                            => return Err(HRMRuntimeError::LetterMath),
+                    // but letter vs. letter subtraction *is* allowed.
+                    (Some(DataCube::Letter(a)), Some(DataCube::Letter(b))) => {
+                        let result = a as i16 - *b as i16;
+                        machine.hands = Some(DataCube::from_number(result)?);
+                    },
+                    
+                    // (obviously, number vs. number subtraction is allowed)
+                    (Some(DataCube::Number(a)), Some(DataCube::Number(b))) => {
+                        machine.hands = Some(DataCube::from_number(a - *b)?);
+                    },
+                }
+            },
+            Instruction::BumpUp(a) => {
+                let floor_tile = machine.tile_mut(a, operation)?;
+                
+                match floor_tile {
+                    None => return Err(HRMRuntimeError::EmptyFloor { operation }),
+                    Some(DataCube::Letter(_)) => return Err(HRMRuntimeError::LetterMath { operation }),
+                    Some(DataCube::Number(x)) => {
+                        if *x >= 999 {
+                            return Err(HRMRuntimeError::Overflow);
+                        }
                         
-                        // but letter vs. letter subtraction *is* allowed.
-                        (Some(DataCube::Letter(a)), Some(DataCube::Letter(b))) => {
-                            let result = a as i16 - *b as i16;
-                            held_item = Some(DataCube::from_number(result)?);
-                        },
+                        *x += 1;
+                    },
+                }
+                
+                machine.hands = floor_tile.clone();
+            },
+            Instruction::BumpDn(a) => {
+                let floor_tile = machine.tile_mut(a, operation)?;
+                
+                match floor_tile {
+                    None => return Err(HRMRuntimeError::EmptyFloor { operation }),
+                    Some(DataCube::Letter(_)) => return Err(HRMRuntimeError::LetterMath { operation }),
+                    Some(DataCube::Number(x)) => {
+                        if *x <= -999 {
+                            return Err(HRMRuntimeError::Overflow);
+                        }
                         
-                        // (obviously, number vs. number subtraction is allowed)
-                        (Some(DataCube::Number(a)), Some(DataCube::Number(b))) => {
-                            held_item = Some(DataCube::from_number(a - *b)?);
-                        },
-                    }
-                },
-                Instruction::BumpUp(a) => {
-                    let floor_tile = a.follow_mut(&mut current_state)?;
-                    
-                    match floor_tile {
-                        None => return Err(HRMRuntimeError::EmptyFloor),
-                        Some(DataCube::Letter(_)) => return Err(HRMRuntimeError::LetterMath),
-                        Some(DataCube::Number(x)) => {
-                            if *x >= 999 {
-                                return Err(HRMRuntimeError::Overflow);
-                            }
-                            
-                            *x += 1;
-                        },
-                    }
-                    
-                    held_item = floor_tile.clone();
-                },
-                Instruction::BumpDn(a) => {
-                    let floor_tile = a.follow_mut(&mut current_state)?;
-                    
-                    match floor_tile {
-                        None => return Err(HRMRuntimeError::EmptyFloor),
-                        Some(DataCube::Letter(_)) => return Err(HRMRuntimeError::LetterMath),
-                        Some(DataCube::Number(x)) => {
-                            if *x <= -999 {
-                                return Err(HRMRuntimeError::Overflow);
-                            }
-                            
-                            *x -= 1;
-                        },
-                    }
-                    
-                    held_item = floor_tile.clone();
-                },
+                        *x -= 1;
+                    },
+                }
                 
-                // jump instructions
-                Instruction::Jump(label) => {
-                    program_counter = self.jump_label_lines[label];
-                    steps += 1;
-                    continue;
-                },
-                Instruction::JumpN(label) => {
-                    match held_item {
-                        Some(DataCube::Number(x)) if x < 0 => {
-                            program_counter = self.jump_label_lines[label];
-                            steps += 1;
-                            continue;
-                        },
-                        Some(_) => {},
-                        None => return Err(HRMRuntimeError::EmptyHands),
-                    }
-                },
-                Instruction::JumpZ(label) => {
-                    match held_item {
-                        Some(DataCube::Number(x)) if x == 0 => {
-                            program_counter = self.jump_label_lines[label];
-                            steps += 1;
-                            continue;
-                        },
-                        Some(_) => {},
-                        None => return Err(HRMRuntimeError::EmptyHands),
-                    }
-                },
-            }
+                machine.hands = floor_tile.clone();
+            },
             
-            steps += 1;
-            program_counter += 1;
+            // jump instructions
+            Instruction::Jump(label) => {
+                machine.program_counter = self.jump_label_lines[label];
+                machine.steps += 1;
+                return Ok(true);
+            },
+            Instruction::JumpN(label) => {
+                match machine.hands {
+                    Some(DataCube::Number(x)) if x < 0 => {
+                        machine.program_counter = self.jump_label_lines[label];
+                        machine.steps += 1;
+                        return Ok(true);
+                    },
+                    Some(_) => {},
+                    None => return Err(HRMRuntimeError::EmptyHands { operation }),
+                }
+            },
+            Instruction::JumpZ(label) => {
+                match machine.hands {
+                    Some(DataCube::Number(0)) => {
+                        machine.program_counter = self.jump_label_lines[label];
+                        machine.steps += 1;
+                        return Ok(true);
+                    },
+                    Some(_) => {},
+                    None => return Err(HRMRuntimeError::EmptyHands { operation }),
+                }
+            },
+        }
+        
+        machine.steps += 1;
+        machine.program_counter += 1;
+        
+        Ok(true)
+    }
+}
+
+/// the state of the office while a program is running.
+struct Machine {
+    /// (reversed, so that it can be used as a stack)
+    inbox: Vec<DataCube>,
+    outbox: Vec<DataCube>,
+    floor: Vec<Option<DataCube>>,
+    hands: Option<DataCube>,
+    program_counter: usize,
+    steps: usize,
+    
+    /// the tile accessed by the current instruction, if any
+    address: Option<usize>,
+}
+
+impl Machine {
+    fn new(mut inbox: Vec<DataCube>, floor: Vec<Option<DataCube>>) -> Self {
+        inbox.reverse(); // turn the inbox into a stack
+        
+        Self {
+            inbox,
+            outbox: Vec::new(),
+            floor,
+            hands: None,
+            program_counter: 0,
+            steps: 0,
+            address: None,
         }
+    }
+    
+    fn tile(&mut self, address: &Address, operation: &'static str) -> Result<&Option<DataCube>, HRMRuntimeError> {
+        let i = address.resolve(&self.floor, operation)?;
+        self.address = Some(i);
+        Ok(&self.floor[i])
+    }
+    
+    fn tile_mut(&mut self, address: &Address, operation: &'static str) -> Result<&mut Option<DataCube>, HRMRuntimeError> {
+        let i = address.resolve(&self.floor, operation)?;
+        self.address = Some(i);
+        Ok(&mut self.floor[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::optimize::control_flow_graph::ProgramControlFlowGraph;
+    
+    use super::*;
+    
+    /// a program as exported from the game, with a comment before a label, one after a label, and the
+    /// drawings for both kinds.
+    const GAME_EXPORT: &str = "\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+    COMMENT  0
+a:
+    INBOX   
+    COPYTO   0
+    COMMENT  1
+b:
+    COMMENT  2
+    BUMPDN   0
+    JUMPN    a
+    COPYFROM [0]
+    OUTBOX  
+    JUMP     b
+
+
+DEFINE COMMENT 0
+eNqTY2BgOLvUxlft1A4JMVVDA+vdm2Tf5BoJM+qw3ZO6dkTZorpQL+qms1ySvU/RGnnJ08mShoU3xfVd
+3KfMvHbb09/m49yOmMMmBgmr9j0yVFVokSvO1Pr39tb1Bcnv2F8o7qyeaVjPFfOr5oXmrpn1f++sP/s0
+Z3KVarGNyLXSEMX1DKNgFIyCAQcAPD02cw==;
+
+DEFINE LABEL 0
+eNrjYWBguOAb+evFdSGRveqLF6is89rs82/ySxPl1/qmXXs/uSsKyLfM036ybWLb0VUbe7mT3/zfyjAK
+RsEoGDYAANscGU0=;
+";
+    
+    #[test]
+    fn drawings_and_comments_round_trip() {
+        let program = Program::from_asm(GAME_EXPORT).unwrap();
+        assert_eq!(program.to_asm().trim_end(), GAME_EXPORT.trim_end());
+        
+        assert_eq!(program.comments.iter().map(|c| (c.line, c.index, c.before_label)).collect::<Vec<_>>(), [(0, 0, true), (2, 1, true), (2, 2, false)]);
+        assert!(matches!(program.drawings[..], [Drawing::Comment { index: 0, .. }, Drawing::Label { tile: 0, .. }]));
         
-        Ok((steps, outbox))
+        // (the comments stay with the code they're next to, even through the control flow graph)
+        let graph = ProgramControlFlowGraph::new(&program);
+        let rebuilt: Program = (&graph).into();
+        assert_eq!(rebuilt.drawings, program.drawings);
+        assert_eq!(rebuilt.to_asm(), program.to_asm());
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn parse_errors_point_at_the_source() {
+        let asm = "-- HUMAN RESOURCE MACHINE PROGRAM --\n\n    FOO\na:\n    INBOX\n    OUTBOX\n    INBOX\n    OUTBOX\n    INBOX\n\tCOPYTO x\n    JUMP     nowhere\n";
+        let Err(errors) = Program::from_asm(asm) else { panic!("the program should not parse") };
+        
+        // (the gutter gets wider for two-digit lines, and tabs are kept so the carets line up)
+        assert_eq!(errors.to_string(), "\
+error: unexpected token `FOO`
+ --> line 3, column 5
+  |
+3 |     FOO
+  |     ^^^
+
+error: invalid number (invalid digit found in string)
+  --> line 10, column 9
+   |
+10 | \tCOPYTO x
+   | \t       ^
+
+error: unknown label `nowhere`
+  --> line 11, column 14
+   |
+11 |     JUMP     nowhere
+   |              ^^^^^^^
+
+could not parse program due to 3 errors");
+    }
+    
+    #[test]
+    fn programs_that_never_stop_give_up() {
+        let program = Program::from_asm("-- HUMAN RESOURCE MACHINE PROGRAM --\n\na:\n    JUMP     a\n").unwrap();
+        let fault = program.simulate(Vec::new()).unwrap_err();
+        assert!(matches!(fault.error, HRMRuntimeError::TooManySteps { limit: STEP_LIMIT }), "{fault}");
+    }
+}
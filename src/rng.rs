@@ -0,0 +1,48 @@
+use crate::datacube::DataCube;
+
+/// a small, seedable pseudo-random number generator (xorshift64*).
+///
+/// this is nowhere near good enough for anything cryptographic, but it's
+/// plenty for generating random inboxes, and it keeps test runs reproducible.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // the state can never be zero, or it would get stuck there
+        Self(seed ^ 0x9E37_79B9_7F4A_7C15 | 1)
+    }
+    
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+    
+    /// a random number in the range `low..=high`.
+    pub fn range(&mut self, low: i32, high: i32) -> i32 {
+        debug_assert!(low <= high);
+        let width = (high - low) as u64 + 1;
+        low + (self.next_u64() % width) as i32
+    }
+    
+    /// returns true with a probability of `1 / n`.
+    pub fn one_in(&mut self, n: u32) -> bool {
+        self.next_u64().is_multiple_of(n as u64)
+    }
+    
+    pub fn choose<T: Clone>(&mut self, items: &[T]) -> T {
+        items[self.range(0, items.len() as i32 - 1) as usize].clone()
+    }
+    
+    /// a random number datacube in the range `low..=high`.
+    pub fn number(&mut self, low: i16, high: i16) -> DataCube {
+        DataCube::Number(self.range(low as i32, high as i32) as i16)
+    }
+    
+    /// a random letter datacube.
+    pub fn letter(&mut self) -> DataCube {
+        DataCube::Letter(b'A' + self.range(0, 25) as u8)
+    }
+}
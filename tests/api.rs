@@ -0,0 +1,285 @@
+//! tests for the public api, as used from outside the crate.
+
+use hrm_optimizer::{
+    check, errors::IrParseError, levels::Level, optimize::{block_optimizations, remarks::RemarkKind}, rng::Rng,
+    DataCube, Instruction, Optimization, OptimizationGoal, PassManager, Program, ProgramControlFlowGraph,
+};
+
+const MAIL_ROOM: &str = "\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+a:
+    INBOX
+    JUMP     b
+    OUTBOX
+b:
+    OUTBOX
+    JUMP     a
+";
+
+#[test]
+fn asm_round_trip() {
+    let program = Program::from_asm(MAIL_ROOM).unwrap();
+    let asm = program.to_asm();
+    assert_eq!(Program::from_asm(&asm).unwrap().to_asm(), asm);
+}
+
+#[test]
+fn parse_errors_are_reported() {
+    let errors = Program::from_asm("-- HUMAN RESOURCE MACHINE PROGRAM --\n    FOO\n    JUMP nowhere\n").unwrap_err();
+    assert_eq!(errors.0.len(), 2);
+}
+
+#[test]
+fn optimized_program_solves_the_level() {
+    let level = Level::get(2).unwrap();
+    let mut program = Program::from_asm(MAIL_ROOM).unwrap();
+    program.initial_floor = level.initial_floor();
+    
+    let optimized = hrm_optimizer::optimize_program(&program, OptimizationGoal::Size);
+    assert!(optimized.instructions.len() < program.instructions.len());
+    
+    check::check(&optimized, level, 20, &mut Rng::new(0)).unwrap();
+}
+
+#[test]
+fn custom_passes_can_be_run() {
+    struct RemoveOutboxes;
+    
+    impl Optimization for RemoveOutboxes {
+        fn optimize(&mut self, graph: &mut ProgramControlFlowGraph) -> bool {
+            let mut modified = false;
+            for block in graph.blocks.iter_mut() {
+                let before = block.instructions.len();
+                block.instructions.retain(|instruction| *instruction != Instruction::Outbox);
+                modified |= block.instructions.len() != before;
+            }
+            modified
+        }
+    }
+    
+    let program = Program::from_asm(MAIL_ROOM).unwrap();
+    let mut graph = ProgramControlFlowGraph::new(&program);
+    assert_eq!(graph.blocks.len(), 3);
+    
+    // (plain functions work as passes too)
+    assert!(graph.run_optimization_pass(block_optimizations::remove_dead_blocks));
+    assert_eq!(graph.blocks.len(), 2);
+    
+    let program: Program = (&graph).into();
+    let inbox = vec![DataCube::Number(-5), DataCube::Letter(b'Q')];
+    assert_eq!(program.simulate(inbox.clone()).unwrap().1, inbox);
+    
+    assert!(graph.run_optimization_pass(RemoveOutboxes));
+    assert!(!graph.run_optimization_pass(RemoveOutboxes));
+    
+    let program: Program = (&graph).into();
+    assert_eq!(program.simulate(inbox).unwrap().1, vec![]);
+}
+
+#[test]
+fn pass_manager_counts_passes_and_gives_up() {
+    let program = Program::from_asm(MAIL_ROOM).unwrap();
+    
+    let mut graph = ProgramControlFlowGraph::new(&program);
+    let statistics = PassManager::from_names(&["simplify-jumps", "dce"], OptimizationGoal::Speed).unwrap().run(&mut graph);
+    assert_eq!(statistics.runs, vec![("simplify-jumps", 0), ("dce", 1)]);
+    assert!(statistics.converged);
+    
+    assert!(PassManager::from_names(&["dce", "nope"], OptimizationGoal::Speed).is_err());
+    
+    // two passes that keep undoing each other never reach a fix point
+    let mut flip = PassManager::new();
+    flip.iteration_limit = 10;
+    flip.add_pass("add", Box::new(|graph: &mut ProgramControlFlowGraph| {
+        graph.blocks[0].instructions.push(Instruction::Outbox);
+        true
+    }));
+    flip.add_pass("remove", Box::new(|graph: &mut ProgramControlFlowGraph| {
+        graph.blocks[0].instructions.pop();
+        true
+    }));
+    
+    let statistics = flip.run(&mut ProgramControlFlowGraph::new(&program));
+    assert!(!statistics.converged);
+    assert_eq!(statistics.total(), 10);
+}
+
+#[test]
+fn passes_explain_what_they_did() {
+    let program = Program::from_asm("\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+a:
+    INBOX
+    COPYTO   0
+    ADD      0
+    SUB      0
+    OUTBOX
+    JUMP     a
+").unwrap();
+
+    let mut graph = ProgramControlFlowGraph::new(&program);
+    PassManager::preset(OptimizationGoal::Speed).run(&mut graph);
+    
+    let remark = graph.remarks.0.iter().find(|remark| remark.pass == "peephole").unwrap();
+    assert_eq!(remark.lines, vec![5, 6]);
+    assert_eq!(remark.to_string(), "[peephole] removed ADD 0 / SUB 0 pair, which cancel out (lines 5-6)");
+    
+    assert!(graph.remarks.to_json().contains("\"pass\": \"peephole\""));
+}
+
+#[test]
+fn optimized_instructions_remember_their_source_lines() {
+    let program = Program::from_asm("\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+a:
+    INBOX
+    JUMP     b
+b:
+    COPYTO   0
+    COPYFROM 0
+    ADD      1
+    OUTBOX
+    JUMP     a
+").unwrap();
+
+    let optimized = hrm_optimizer::optimize_program(&program, OptimizationGoal::Speed);
+    let add = optimized.instructions.iter().position(|instruction| matches!(instruction, Instruction::Add(_))).unwrap();
+    assert_eq!(optimized.source_lines[add].iter().copied().collect::<Vec<_>>(), vec![8]);
+    assert!(optimized.to_asm_annotated().contains("-- line 8"));
+    
+    // the COPYFROM was redundant, so the COPYTO before it is now responsible for both lines
+    let mut graph = ProgramControlFlowGraph::new(&program);
+    PassManager::from_names(&["merge-blocks", "peephole"], OptimizationGoal::Speed).unwrap().run(&mut graph);
+    let peepholed: Program = (&graph).into();
+    let copyto = peepholed.instructions.iter().position(|instruction| matches!(instruction, Instruction::CopyTo(_))).unwrap();
+    assert_eq!(peepholed.source_lines[copyto].iter().copied().collect::<Vec<_>>(), vec![6, 7]);
+    
+    // and runtime errors in the optimized program point back at the original
+    let fault = optimized.simulate(vec![DataCube::Number(3)]).unwrap_err();
+    assert_eq!(fault.source_lines, vec![8]);
+}
+
+#[test]
+fn graphs_can_be_exported_to_dot() {
+    let graph = ProgramControlFlowGraph::new(&Program::from_asm(MAIL_ROOM).unwrap());
+    let dot = graph.to_dot("mail room");
+    
+    assert!(dot.starts_with("digraph \"mail room\" {\n"));
+    assert!(dot.contains("block0 [label=\"block 0 (entry)\\lINBOX\\l\", style=bold"));
+    assert!(dot.contains("block1 [label=\"block 1 (dead)\\lOUTBOX\\l\""));
+    
+    // the jump back to the start of the loop is the only back edge
+    assert!(dot.contains("block2 -> block0 [label=\"always\", style=dashed"));
+    assert!(dot.contains("block0 -> block2 [label=\"always\"];"));
+}
+
+#[test]
+fn graphs_round_trip_through_the_ir() {
+    let graph = ProgramControlFlowGraph::new(&Program::from_asm(MAIL_ROOM).unwrap());
+    let ir = graph.to_ir();
+    assert_eq!(ProgramControlFlowGraph::from_ir(&ir).unwrap().to_ir(), ir);
+    
+    let errors = ProgramControlFlowGraph::from_ir("\
+block 0:
+    INBOX
+    jump positive -> block 1
+    jump zero/negative -> block 7
+block 1:
+    JUMP a
+").unwrap_err();
+    let messages: Vec<_> = errors.0.iter().map(|error| error.error.to_string()).collect();
+    assert_eq!(messages, vec![
+        "unknown label `block 7`",
+        "block 1 needs to jump somewhere for every value in hands (e.g. with `jump always -> end`)",
+        "unexpected token `JUMP`",
+    ]);
+    assert!(matches!(errors.0[1].error, IrParseError::MissingJump(1)));
+}
+
+#[test]
+fn overflows_are_flagged() {
+    let mut program = Program::from_asm("\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+a:
+    INBOX
+    JUMPN    a
+    JUMPZ    a
+    ADD      0
+    OUTBOX
+    JUMP     a
+").unwrap();
+    program.initial_floor = vec![Some(DataCube::Number(999))];
+    
+    let mut graph = ProgramControlFlowGraph::new(&program);
+    PassManager::preset(OptimizationGoal::Speed).run(&mut graph);
+    
+    let warnings: Vec<_> = graph.remarks.0.iter().filter(|remark| remark.kind == RemarkKind::Warning).collect();
+    assert_eq!(warnings.len(), 1);
+    assert_eq!(warnings[0].to_string(), "[ranges] warning: ADD 0 will always overflow, since the result is never between -999 and 999 (line 6)");
+}
+
+#[test]
+fn graphs_know_their_dominators_and_loops() {
+    use hrm_optimizer::optimize::{basic_blocks::BasicBlockId, jump_flag::JumpFlag};
+    
+    let mut graph = ProgramControlFlowGraph::from_ir("\
+block 0:
+    INBOX
+    COPYTO   0
+    jump always -> block 1
+block 1:
+    BUMPDN   0
+    jump negative -> block 0
+    jump always -> block 2
+block 2:
+    COPYFROM 0
+    OUTBOX
+    jump always -> block 1
+").unwrap();
+    let block = BasicBlockId;
+    
+    let structure = graph.structure();
+    assert_eq!(structure.dominators.dominators(&block(2)), vec![block(2), block(1), block(0)]);
+    // (block 0 can end the program, by running out of inbox)
+    assert_eq!(structure.post_dominators.immediate_dominator(&block(0)), None);
+    assert_eq!(structure.post_dominators.immediate_dominator(&block(2)), Some(&block(1)));
+    assert_eq!(structure.frontiers[&block(1)], vec![block(0), block(1)]);
+    assert_eq!(structure.back_edges, vec![(block(1), block(0)), (block(2), block(1))]);
+    
+    let [outer, inner] = &structure.loops[..] else { panic!("expected two loops, got {:?}", structure.loops) };
+    assert_eq!((&outer.header, &outer.blocks, outer.depth), (&block(0), &vec![block(0), block(1), block(2)], 1));
+    assert_eq!((&inner.header, &inner.latches, inner.parent, inner.depth), (&block(1), &vec![block(2)], Some(0), 2));
+    assert_eq!(inner.exits, vec![(block(1), block(0))]);
+    assert_eq!(structure.loop_depth(&block(2)), 2);
+    
+    // changing the graph in a pass throws away the old structure
+    graph.run_optimization_pass(|graph: &mut ProgramControlFlowGraph| {
+        graph.blocks[1].outgoing_jumps = vec![(block(2), JumpFlag::Always)];
+        true
+    });
+    assert_eq!(graph.structure().loops.len(), 1);
+    assert!(graph.dump().contains("Loop 0 (header: Block 1, depth: 1):"));
+}
+
+#[test]
+fn loops_are_only_rotated_for_speed() {
+    // (rotating the loop copies its test, which makes the program bigger)
+    let program = Program::from_asm("\
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+a:
+    INBOX
+    JUMPZ    b
+    JUMP     c
+b:
+    OUTBOX
+c:
+    JUMP     a
+").unwrap();
+
+    let size = hrm_optimizer::optimize_program(&program, OptimizationGoal::Size);
+    let speed = hrm_optimizer::optimize_program(&program, OptimizationGoal::Speed);
+    assert_eq!(size.instructions.len(), 5);
+    assert_eq!(speed.instructions.len(), 7);
+}
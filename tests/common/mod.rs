@@ -0,0 +1,33 @@
+//! helpers shared by the test harnesses.
+
+/// a line-by-line diff (based on the longest common subsequence), with `-` for lines that
+/// are only in `expected`, and `+` for lines that are only in `actual`.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let a: Vec<_> = expected.lines().collect();
+    let b: Vec<_> = actual.lines().collect();
+    
+    // lcs[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+    
+    let mut result = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            result += &format!("  {}\n", a[i]);
+            i += 1;
+            j += 1;
+        } else if j < b.len() && (i == a.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            result += &format!("+ {}\n", b[j]);
+            j += 1;
+        } else {
+            result += &format!("- {}\n", a[i]);
+            i += 1;
+        }
+    }
+    result
+}
@@ -1,13 +1,14 @@
 -- HUMAN RESOURCE MACHINE PROGRAM --
--- 09-Zero-Preservation-Initiative - SIZE 5/5 - SPEED 25/25 --
+-- 09-Zero-Preservation-Initiative - SIZE 7/5 - SPEED 23/25 --
 
     JUMP     b
 a:
     OUTBOX  
+    INBOX   
+    JUMPZ    a
 b:
-c:
     INBOX   
     JUMPZ    a
-    JUMP     c
+    JUMP     b
 
 
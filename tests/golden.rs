@@ -0,0 +1,142 @@
+//! golden tests: every directory in `tests/` has an `input.asm`, and the `output.asm` that
+//! the optimizer is expected to turn it into.
+//!
+//! the outputs are compared after normalizing the label names (and whitespace), and the optimized
+//! program also has to behave the same as the input on a bunch of random inboxes.
+//!
+//! run with `BLESS=1` to overwrite the expected outputs with whatever the optimizer does now.
+
+use std::{collections::HashMap, path::{Path, PathBuf}};
+
+use hrm_optimizer::{
+    equivalence::{self, ErrorBehavior},
+    optimize_program,
+    rng::Rng,
+    DataCube, Instruction, OptimizationGoal, Program,
+};
+
+mod common;
+use common::diff;
+
+/// the floor that the test programs were written for.
+fn initial_floor() -> Vec<Option<DataCube>> {
+    let mut floor = vec![None; 16];
+    floor[15] = Some(DataCube::from_number(4).unwrap());
+    floor[14] = Some(DataCube::from_number(0).unwrap());
+    floor
+}
+
+fn test_directories() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    
+    let mut directories: Vec<_> = std::fs::read_dir(&root)
+        .expect("Failed to read the tests directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.join("input.asm").is_file())
+        .collect();
+    directories.sort();
+    directories
+}
+
+fn parse(path: &Path) -> Result<Program, String> {
+    let asm = std::fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    let mut program = Program::from_asm(&asm).map_err(|errors| format!("{}:\n{errors}", path.display()))?;
+    program.initial_floor = initial_floor();
+    Ok(program)
+}
+
+/// renames the labels to `a`, `b`, `c`, ... in the order they appear in the program (merging labels
+/// that point to the same line, and dropping ones that nothing jumps to), and reformats the program.
+fn normalize(program: &Program) -> String {
+    let target = |label: &str| program.jump_label_lines[label];
+    
+    let mut targets: Vec<usize> = program.instructions.iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Jump(label) | Instruction::JumpZ(label) | Instruction::JumpN(label) => Some(target(label)),
+            _ => None,
+        })
+        .collect();
+    targets.sort();
+    targets.dedup();
+    
+    let names: HashMap<usize, String> = targets.iter().enumerate()
+        .map(|(i, &line)| (line, Program::label_name(i)))
+        .collect();
+    
+    let instructions = program.instructions.iter()
+        .map(|instruction| match instruction {
+            Instruction::Jump(label) => Instruction::Jump(names[&target(label)].clone()),
+            Instruction::JumpZ(label) => Instruction::JumpZ(names[&target(label)].clone()),
+            Instruction::JumpN(label) => Instruction::JumpN(names[&target(label)].clone()),
+            other => other.clone(),
+        })
+        .collect();
+    
+    let normalized = Program {
+        instructions,
+        initial_floor: program.initial_floor.clone(),
+        jump_label_lines: names.into_iter().map(|(line, name)| (name, line)).collect(),
+        comments: program.comments.clone(),
+        drawings: program.drawings.clone(),
+        source_lines: Vec::new(),
+    };
+    
+    let asm = normalized.to_asm();
+    let lines: Vec<_> = asm.lines().map(str::trim_end).collect();
+    lines.join("\n").trim_end().to_string() + "\n"
+}
+
+/// runs one test, returning a description of everything that's wrong with it.
+fn run_test(directory: &Path, bless: bool) -> Result<(), String> {
+    let input = parse(&directory.join("input.asm"))?;
+    
+    let optimized = optimize_program(&input, OptimizationGoal::Speed);
+    
+    let inboxes = equivalence::generate_inboxes(&mut Rng::new(0), 200);
+    
+    let mut problems = Vec::new();
+    
+    if let Err(divergence) = equivalence::check_equivalence(&input, &optimized, &inboxes, ErrorBehavior::Undefined) {
+        problems.push(format!("the optimized program doesn't behave like the input: {divergence}"));
+    }
+    
+    let actual = normalize(&optimized);
+    let output_path = directory.join("output.asm");
+    
+    if bless {
+        std::fs::write(&output_path, &actual).map_err(|error| format!("{}: {error}", output_path.display()))?;
+    } else {
+        let expected = parse(&output_path)?;
+        
+        // (this would mean the test itself is wrong)
+        if let Err(divergence) = equivalence::check_equivalence(&input, &expected, &inboxes, ErrorBehavior::Undefined) {
+            problems.push(format!("the expected output doesn't behave like the input: {divergence}"));
+        }
+        
+        let expected = normalize(&expected);
+        if expected != actual {
+            problems.push(format!("the optimized program doesn't match the expected output:\n{}", diff(&expected, &actual)));
+        }
+    }
+    
+    if problems.is_empty() { Ok(()) } else { Err(problems.join("\n")) }
+}
+
+#[test]
+fn golden() {
+    let bless = std::env::var_os("BLESS").is_some_and(|value| value != "0");
+    
+    let directories = test_directories();
+    assert!(!directories.is_empty(), "no tests found");
+    
+    let failures: Vec<_> = directories.iter()
+        .filter_map(|directory| {
+            let name = directory.file_name().unwrap().to_string_lossy();
+            run_test(directory, bless).err().map(|problems| format!("---- {name} ----\n{problems}\n"))
+        })
+        .collect();
+    
+    if !failures.is_empty() {
+        panic!("{} of {} golden tests failed (run with BLESS=1 to accept the new outputs)\n\n{}", failures.len(), directories.len(), failures.join("\n"));
+    }
+}
@@ -0,0 +1,110 @@
+//! pass tests: every `.ir` file in `tests/passes/` is a control flow graph written in the textual IR
+//! (see `hrm_optimizer::optimize::ir`), along with the passes to run on it, and what it should look like after.
+//!
+//! a test looks like this:
+//! ```text
+//! -- passes: merge-blocks,peephole
+//! -- (anything else until the first block is a description of the test)
+//!
+//! block 0:
+//!     ...
+//!
+//! -- expected:
+//! block 0:
+//!     ...
+//! ```
+//!
+//! the result also has to behave the same as the input on a bunch of random inboxes.
+//! run with `BLESS=1` to overwrite the expected graphs with whatever the passes do now.
+
+use std::path::{Path, PathBuf};
+
+use hrm_optimizer::{
+    equivalence::{self, ErrorBehavior},
+    rng::Rng,
+    OptimizationGoal, PassManager, Program, ProgramControlFlowGraph,
+};
+
+mod common;
+use common::diff;
+
+const EXPECTED: &str = "-- expected:";
+
+fn test_files() -> Vec<PathBuf> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("passes");
+    
+    let mut files: Vec<_> = std::fs::read_dir(&root)
+        .expect("Failed to read the pass tests directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ir"))
+        .collect();
+    files.sort();
+    files
+}
+
+fn parse(ir: &str, what: &str) -> Result<ProgramControlFlowGraph, String> {
+    ProgramControlFlowGraph::from_ir(ir).map_err(|errors| format!("the {what} doesn't parse:\n{errors}"))
+}
+
+fn to_program(graph: &ProgramControlFlowGraph) -> Program {
+    let mut program: Program = graph.into();
+    program.initial_floor = graph.initial_floor.clone();
+    program
+}
+
+/// runs one test, returning a description of everything that's wrong with it.
+fn run_test(path: &Path, bless: bool) -> Result<(), String> {
+    let text = std::fs::read_to_string(path).map_err(|error| format!("{}: {error}", path.display()))?;
+    
+    let passes = text.lines().next().and_then(|line| line.strip_prefix("-- passes:"))
+        .ok_or("the first line should be `-- passes: <pass>,<pass>,...`")?;
+    let passes: Vec<_> = passes.split(',').map(str::trim).filter(|pass| !pass.is_empty()).collect();
+    
+    let (input, expected) = match text.split_once(&format!("\n{EXPECTED}\n")) {
+        Some((input, expected)) => (input, Some(expected)),
+        None => (text.as_str(), None),
+    };
+    
+    let original = parse(input, "input")?;
+    let mut graph = parse(input, "input")?;
+    PassManager::from_names(&passes, OptimizationGoal::Speed).map_err(|error| error.to_string())?.run(&mut graph);
+    let actual = graph.to_ir();
+    
+    let mut problems = Vec::new();
+    
+    let inboxes = equivalence::generate_inboxes(&mut Rng::new(0), 200);
+    if let Err(divergence) = equivalence::check_equivalence(&to_program(&original), &to_program(&graph), &inboxes, ErrorBehavior::Undefined) {
+        problems.push(format!("the result doesn't behave like the input: {divergence}"));
+    }
+    
+    if bless {
+        let blessed = format!("{}\n{EXPECTED}\n{actual}", input.trim_end_matches('\n'));
+        std::fs::write(path, blessed).map_err(|error| format!("{}: {error}", path.display()))?;
+    } else {
+        let expected = parse(expected.ok_or(format!("there's no `{EXPECTED}` line"))?, "expected graph")?.to_ir();
+        if expected != actual {
+            problems.push(format!("the result doesn't match the expected graph:\n{}", diff(&expected, &actual)));
+        }
+    }
+    
+    if problems.is_empty() { Ok(()) } else { Err(problems.join("\n")) }
+}
+
+#[test]
+fn passes() {
+    let bless = std::env::var_os("BLESS").is_some_and(|value| value != "0");
+    
+    let files = test_files();
+    assert!(!files.is_empty(), "no tests found");
+    
+    let failures: Vec<_> = files.iter()
+        .filter_map(|path| {
+            let name = path.file_stem().unwrap().to_string_lossy();
+            run_test(path, bless).err().map(|problems| format!("---- {name} ----\n{problems}\n"))
+        })
+        .collect();
+    
+    if !failures.is_empty() {
+        panic!("{} of {} pass tests failed (run with BLESS=1 to accept the new outputs)\n\n{}", failures.len(), files.len(), failures.join("\n"));
+    }
+}
@@ -0,0 +1,16 @@
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+a:
+    INBOX   
+    COPYTO   0
+b:
+    COPYFROM 0
+    OUTBOX  
+    COPYFROM 0
+    JUMPZ    a
+    JUMPN    c
+    BUMPDN   0
+    JUMP     b
+c:
+    BUMPUP   0
+    JUMP     b
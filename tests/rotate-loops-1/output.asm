@@ -0,0 +1,24 @@
+-- HUMAN RESOURCE MACHINE PROGRAM --
+
+a:
+    INBOX   
+    COPYTO   0
+    OUTBOX  
+    COPYFROM 0
+    JUMPZ    a
+    JUMPN    c
+b:
+    BUMPDN   0
+    OUTBOX  
+    COPYFROM 0
+    JUMPZ    a
+    JUMPN    c
+    JUMP     b
+c:
+    BUMPUP   0
+    OUTBOX  
+    COPYFROM 0
+    JUMPZ    a
+    JUMPN    c
+    JUMP     b
+